@@ -41,14 +41,12 @@ use module_bindings::{
     DbConnection,
     EventContext as ModuleEventContext,
     Player as SdbPlayer,
-    Entity as SdbEntity,
-    Circle as SdbCircle,
+    PlayerStats as SdbPlayerStats,
     RemoteTables,
     ErrorContext as ModuleErrorContext,
     SubscriptionEventContext as ModuleSubscriptionEventContext,
     player_table::PlayerTableAccess,
-    circle_table::CircleTableAccess,
-    entity_table::EntityTableAccess,
+    player_stats_table::PlayerStatsTableAccess,
 };
 
 use log::{error, warn, info};
@@ -130,13 +128,9 @@ async fn main() -> Result<()> {
     register_player_callbacks(&sdb_connection, aptos_context.clone(), runtime_handle.clone());
     info!("Player callbacks registered.");
 
-    info!("Registering entity callbacks with SpacetimeDB...");
-    register_entity_callbacks(&sdb_connection, aptos_context.clone(), runtime_handle.clone());
-    info!("Entity callbacks registered.");
-
-    info!("Registering circle callbacks with SpacetimeDB...");
-    register_circle_callbacks(&sdb_connection, aptos_context.clone(), runtime_handle.clone());
-    info!("Circle callbacks registered.");
+    info!("Registering player stats callbacks with SpacetimeDB...");
+    register_player_stats_callbacks(&sdb_connection, aptos_context.clone(), runtime_handle.clone());
+    info!("Player stats callbacks registered.");
 
 
     info!("Subscribing to SpacetimeDB game tables...");
@@ -206,53 +200,30 @@ fn register_player_callbacks(
     });
 }
 
-fn register_entity_callbacks(
+fn register_player_stats_callbacks(
     sdb_conn: &DbConnection,
     aptos_ctx: Arc<AptosContext>,
     runtime_handle: TokioRuntimeHandle,
 ) {
-    let aptos_ctx_entity_update = aptos_ctx.clone();
-    let runtime_handle_entity_update = runtime_handle.clone();
-    sdb_conn.db.entity().on_update(move |ctx: &ModuleEventContext, old_entity: &SdbEntity, new_entity: &SdbEntity| {
-        if old_entity.mass == new_entity.mass {
-            return;
-        }
-        info!("[SDB Entity Update] Entity ID: {} mass changed from {} to {}. Checking player.", new_entity.entity_id, old_entity.mass, new_entity.mass);
-        if let Some(player_id) = ctx.db().circle().iter().find(|c| c.entity_id == new_entity.entity_id).map(|circle| circle.player_id) {
-            if let Some(player_row) = ctx.db().player().iter().find(|p| p.player_id == player_id) {
-                info!("[SDB Entity Update] Entity {} belongs to Player ID {}. Processing state change.", new_entity.entity_id, player_id);
-                process_player_state_change(&player_row, &ctx.db(), aptos_ctx_entity_update.clone(), runtime_handle_entity_update.clone());
-            } else {
-                warn!("[SDB Entity Update] Entity {} associated with player_id {} but player row not found.", new_entity.entity_id, player_id);
-            }
+    let aptos_ctx_stats_insert = aptos_ctx.clone();
+    let runtime_handle_stats_insert = runtime_handle.clone();
+    sdb_conn.db.player_stats().on_insert(move |ctx: &ModuleEventContext, new_stats: &SdbPlayerStats| {
+        if let Some(player_row) = ctx.db().player().iter().find(|p| p.player_id == new_stats.player_id) {
+            process_player_state_change(&player_row, &ctx.db(), aptos_ctx_stats_insert.clone(), runtime_handle_stats_insert.clone());
         }
     });
-}
 
-fn register_circle_callbacks(
-    sdb_conn: &DbConnection,
-    aptos_ctx: Arc<AptosContext>,
-    runtime_handle: TokioRuntimeHandle,
-) {
-    let aptos_ctx_c_insert = aptos_ctx.clone();
-    let runtime_handle_c_insert = runtime_handle.clone();
-    sdb_conn.db.circle().on_insert(move |ctx: &ModuleEventContext, new_circle: &SdbCircle| {
-        info!("[SDB Circle Insert] New circle for Entity ID: {} linked to Player ID: {}. Re-evaluating player mass.", new_circle.entity_id, new_circle.player_id);
-        if let Some(player_row) = ctx.db().player().iter().find(|p| p.player_id == new_circle.player_id) {
-            process_player_state_change(&player_row, &ctx.db(), aptos_ctx_c_insert.clone(), runtime_handle_c_insert.clone());
-        } else {
-            warn!("[SDB Circle Insert] Circle for Player ID {} inserted, but Player row not found.", new_circle.player_id);
+    let aptos_ctx_stats_update = aptos_ctx.clone();
+    let runtime_handle_stats_update = runtime_handle.clone();
+    sdb_conn.db.player_stats().on_update(move |ctx: &ModuleEventContext, old_stats: &SdbPlayerStats, new_stats: &SdbPlayerStats| {
+        if old_stats.current_life_peak_mass == new_stats.current_life_peak_mass {
+            return;
         }
-    });
-
-    let aptos_ctx_c_delete = aptos_ctx.clone();
-    let runtime_handle_c_delete = runtime_handle.clone();
-    sdb_conn.db.circle().on_delete(move |ctx: &ModuleEventContext, deleted_circle: &SdbCircle| {
-        info!("[SDB Circle Delete] Circle for Entity ID: {} (Player ID: {}) was deleted. Re-evaluating player mass.", deleted_circle.entity_id, deleted_circle.player_id);
-        if let Some(player_row) = ctx.db().player().iter().find(|p| p.player_id == deleted_circle.player_id) {
-             process_player_state_change(&player_row, &ctx.db(), aptos_ctx_c_delete.clone(), runtime_handle_c_delete.clone());
+        info!("[SDB PlayerStats Update] Player ID: {} peak mass changed from {} to {}. Checking player.", new_stats.player_id, old_stats.current_life_peak_mass, new_stats.current_life_peak_mass);
+        if let Some(player_row) = ctx.db().player().iter().find(|p| p.player_id == new_stats.player_id) {
+            process_player_state_change(&player_row, &ctx.db(), aptos_ctx_stats_update.clone(), runtime_handle_stats_update.clone());
         } else {
-            info!("[SDB Circle Delete] Circle for Player ID {} deleted, but Player row not found (player might have disconnected/been deleted).", deleted_circle.player_id);
+            warn!("[SDB PlayerStats Update] Stats for Player ID {} updated, but Player row not found.", new_stats.player_id);
         }
     });
 }
@@ -265,7 +236,7 @@ fn process_player_state_change(
 ) {
     info!("[ProcessPlayerStateChange] Called for Player ID: {}, Name: '{}', Aptos Addr: '{}'", player_row.player_id, player_row.name, player_row.aptos_address);
 
-    let current_total_mass = calculate_player_total_mass(player_row.player_id, db_view);
+    let current_total_mass = player_peak_mass(player_row.player_id, db_view);
     let p_id = player_row.player_id;
     let p_aptos_address = player_row.aptos_address.clone();
 
@@ -296,32 +267,26 @@ fn process_player_state_change(
 fn subscribe_to_game_tables(sdb_conn: &DbConnection) {
     let queries = vec![
         "SELECT * FROM player".to_string(),
-        "SELECT * FROM circle".to_string(),
-        "SELECT * FROM entity".to_string(),
+        "SELECT * FROM player_stats".to_string(),
     ];
     sdb_conn.subscription_builder().on_applied(on_subscription_applied).on_error(on_subscription_error).subscribe(queries);
-    info!("Subscription request for player, circle, and entity tables sent.");
+    info!("Subscription request for player and player_stats tables sent.");
 }
 
 fn on_subscription_applied(_ctx: &ModuleSubscriptionEventContext) { info!("Game table subscriptions applied successfully."); }
 fn on_subscription_error(_ctx: &ModuleErrorContext, err: spacetimedb_sdk::Error) { error!("Failed to apply game table subscriptions: {:?}", err); }
 
-fn calculate_player_total_mass(player_id_to_find: u32, db_view: &RemoteTables) -> u32 {
-    let mut total_mass = 0;
-    let mut circles_found = 0;
-    for sdb_circle_row in db_view.circle().iter().filter(|c| c.player_id == player_id_to_find) {
-        circles_found += 1;
-        if let Some(sdb_entity_row) = db_view.entity().iter().find(|e| e.entity_id == sdb_circle_row.entity_id) {
-            total_mass += sdb_entity_row.mass;
-        } else {
-            warn!("[CalculateMass] Player ID {}: Entity NOT FOUND for Circle Entity ID: {} (belongs to player_id: {})", player_id_to_find, sdb_circle_row.entity_id, player_id_to_find);
+/// Highest total mass the player reached in their current life, as tracked by
+/// the module. Read from `player_stats` because `entity` and `circle` rows are
+/// only visible to the players who can see them.
+fn player_peak_mass(player_id_to_find: u32, db_view: &RemoteTables) -> u32 {
+    match db_view.player_stats().player_id().find(&player_id_to_find) {
+        Some(stats) => stats.current_life_peak_mass,
+        None => {
+            info!("[PeakMass] Player ID {}: No player_stats row found in db_view.", player_id_to_find);
+            0
         }
     }
-    if circles_found == 0 && player_id_to_find != 0 {
-        info!("[CalculateMass] Player ID {}: No circles found in db_view.", player_id_to_find);
-    }
-    info!("[CalculateMass] Player ID {}: Calculated total mass = {}", player_id_to_find, total_mass);
-    total_mass
 }
 
 async fn check_and_grant_skin(
@@ -0,0 +1,183 @@
+//! Measures one `move_all_players` tick, `World::step` followed by
+//! `World::resolve_contacts`, for a range of world sizes and prints the
+//! results as CSV.
+//!
+//! ```shell
+//! cargo bench --bench tick
+//! cargo bench --bench tick -- players=10,100,500 food=600 splits=1,16 ticks=50
+//! ```
+
+use agario_sim::{Entity, EntityKind, Input, SimConfig, Vec2, World};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const WORLD_SIZE: f32 = 1000.0;
+const VIRUS_COUNT: u32 = 12;
+const TICK_DT: f32 = 0.05;
+
+/// Small xorshift so runs are reproducible without pulling in `rand`.
+struct Rng(u64);
+
+impl Rng {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + self.next_f32() * (max - min)
+    }
+}
+
+struct Params {
+    players: Vec<u32>,
+    food: Vec<u32>,
+    splits: Vec<u32>,
+    ticks: u32,
+}
+
+impl Params {
+    fn from_args() -> Self {
+        let mut params = Params {
+            players: vec![10, 50, 100, 250, 500],
+            food: vec![600],
+            splits: vec![1, 4, 16],
+            ticks: 20,
+        };
+        // cargo passes `--bench` to harness = false targets
+        for arg in std::env::args().skip(1).filter(|a| !a.starts_with("--")) {
+            let (key, value) = arg
+                .split_once('=')
+                .unwrap_or_else(|| panic!("Expected key=value, got {arg}"));
+            let list = || -> Vec<u32> {
+                value
+                    .split(',')
+                    .map(|v| v.parse().expect("Expected a number"))
+                    .collect()
+            };
+            match key {
+                "players" => params.players = list(),
+                "food" => params.food = list(),
+                "splits" => params.splits = list(),
+                "ticks" => params.ticks = value.parse().expect("Expected a number"),
+                _ => panic!("Unknown parameter {key}"),
+            }
+        }
+        params
+    }
+}
+
+/// `players` players with `circles_per_player` circles each, `food` food and a
+/// few viruses, all at random positions. Returns the world and every player's input.
+fn build_world(
+    rng: &mut Rng,
+    players: u32,
+    food: u32,
+    circles_per_player: u32,
+) -> (World, HashMap<u32, Input>) {
+    let mut world = World::new(WORLD_SIZE);
+    let mut inputs = HashMap::new();
+    let mut next_id = 1;
+    let mut insert = |world: &mut World, position: Vec2, mass: u32, kind: EntityKind| {
+        world.insert(Entity {
+            entity_id: next_id,
+            position,
+            mass,
+            kind,
+        });
+        next_id += 1;
+    };
+
+    for player_id in 1..=players {
+        let center = Vec2::new(rng.range(50.0, 950.0), rng.range(50.0, 950.0));
+        let angle = rng.range(0.0, std::f32::consts::TAU);
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        inputs.insert(
+            player_id,
+            Input {
+                direction,
+                speed: 1.0,
+            },
+        );
+        for _ in 0..circles_per_player {
+            let offset = Vec2::new(rng.range(-30.0, 30.0), rng.range(-30.0, 30.0));
+            let kind = EntityKind::Circle {
+                player_id,
+                direction,
+                speed: 1.0,
+                split_age_sec: rng.range(0.0, 10.0),
+            };
+            insert(
+                &mut world,
+                center + offset,
+                rng.range(15.0, 200.0) as u32,
+                kind,
+            );
+        }
+    }
+    for _ in 0..food {
+        let position = Vec2::new(rng.range(5.0, 995.0), rng.range(5.0, 995.0));
+        insert(
+            &mut world,
+            position,
+            rng.range(2.0, 4.0) as u32,
+            EntityKind::Food,
+        );
+    }
+    for _ in 0..VIRUS_COUNT {
+        let position = Vec2::new(rng.range(20.0, 980.0), rng.range(20.0, 980.0));
+        insert(&mut world, position, 100, EntityKind::Virus);
+    }
+    (world, inputs)
+}
+
+/// One tick: movement and collision detection, then consumption of the contacts.
+fn tick(world: &mut World, config: &SimConfig, inputs: &HashMap<u32, Input>) -> usize {
+    let contacts = world.step(config, TICK_DT, inputs);
+    world.resolve_contacts(contacts).len()
+}
+
+fn main() {
+    let params = Params::from_args();
+    let config = SimConfig::default();
+    println!(
+        "players,food,circles_per_player,entities,ticks,mean_us,min_us,max_us,contacts_per_tick"
+    );
+    for &players in &params.players {
+        for &food in &params.food {
+            for &splits in &params.splits {
+                let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+                let (world, inputs) = build_world(&mut rng, players, food, splits);
+                // Warm up caches and the allocator
+                tick(&mut world.clone(), &config, &inputs);
+
+                let mut timings = Vec::new();
+                let mut contacts = 0;
+                for _ in 0..params.ticks {
+                    // Every tick starts from the same world so the numbers are comparable
+                    let mut world = world.clone();
+                    let start = Instant::now();
+                    contacts += tick(&mut world, &config, &inputs);
+                    timings.push(start.elapsed());
+                }
+
+                let total: Duration = timings.iter().sum();
+                let ticks = timings.len().max(1) as u32;
+                println!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    players,
+                    food,
+                    splits,
+                    world.entities.len(),
+                    params.ticks,
+                    (total / ticks).as_micros(),
+                    timings.iter().min().unwrap_or(&Duration::ZERO).as_micros(),
+                    timings.iter().max().unwrap_or(&Duration::ZERO).as_micros(),
+                    contacts / ticks as usize,
+                );
+            }
+        }
+    }
+}
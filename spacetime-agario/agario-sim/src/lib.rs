@@ -0,0 +1,214 @@
+//! The agar.io rules and physics, free of any SpacetimeDB types so they can be
+//! unit tested natively. The `spacetime-module` crate loads the tables of an
+//! arena into a `World`, steps it and writes the result back.
+
+pub mod math;
+pub mod scalar;
+pub mod spatial_grid;
+pub mod vec2;
+pub mod world;
+
+pub use scalar::{Fixed, Scalar};
+pub use vec2::{FixedVec2, Vec2, Vector2, FIXED_POINT_FRACTION_BITS, MAX_FIXED_POINT_COORDINATE};
+pub use world::{Contact, Entity, EntityKind, Input, Split, World};
+
+/// Speeds, velocities and forces are expressed per tick of this many seconds,
+/// `World::step` scales them to the time that actually passed.
+pub const REFERENCE_TICK_SEC: f32 = 0.05;
+
+/// Cell size of the collision broad-phase grid
+pub const COLLISION_GRID_CELL_SIZE: f32 = 50.0;
+
+/// The subset of the module's `Config` the simulation needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimConfig {
+    pub start_player_mass: u32,
+    pub start_player_speed: u32,
+    pub minimum_safe_mass_ratio: f32,
+    /// Share of the smaller circle's area the bigger one has to cover to eat it,
+    /// at least 0.5 so the smaller circle's center is always inside the bigger one
+    pub eat_overlap_fraction: f32,
+    pub circle_decay_factor: f32,
+    pub min_mass_to_split: u32,
+    pub max_circles_per_player: u32,
+    pub virus_pop_max_pieces: u32,
+    pub virus_pop_min_piece_mass: u32,
+    pub split_recombine_delay_sec: f32,
+    pub split_grav_pull_before_recombine_sec: f32,
+    pub allowed_split_circle_overlap_pct: f32,
+    pub self_collision_speed: f32,
+    pub eject_velocity_decay: f32,
+    /// Run movement on `FixedVec2` instead of `f32`, so a recorded input
+    /// stream replays to the same positions on every platform. Positions
+    /// and velocities are stored on the `FIXED_POINT_FRACTION_BITS` grid
+    pub fixed_point_positions: bool,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            start_player_mass: 15,
+            start_player_speed: 10,
+            minimum_safe_mass_ratio: 0.85,
+            eat_overlap_fraction: 0.75,
+            circle_decay_factor: 0.99,
+            min_mass_to_split: 30,
+            max_circles_per_player: 16,
+            virus_pop_max_pieces: 8,
+            virus_pop_min_piece_mass: 15,
+            split_recombine_delay_sec: 5.0,
+            split_grav_pull_before_recombine_sec: 2.0,
+            allowed_split_circle_overlap_pct: 0.9,
+            self_collision_speed: 0.05,
+            eject_velocity_decay: 0.85,
+            fixed_point_positions: false,
+        }
+    }
+}
+
+pub fn mass_to_radius(mass: u32) -> f32 {
+    (mass as f32).sqrt()
+}
+
+pub fn mass_to_max_move_speed<S: Scalar>(config: &SimConfig, mass: u32) -> S {
+    S::from_u32(2) * S::from_u32(config.start_player_speed)
+        / (S::ONE + (S::from_u32(mass) / S::from_u32(config.start_player_mass)).sqrt())
+}
+
+/// Whether the two circles overlap enough for the bigger one to eat the
+/// smaller one, see `SimConfig::eat_overlap_fraction`.
+pub fn is_overlapping(
+    config: &SimConfig,
+    a_position: Vec2,
+    a_mass: u32,
+    b_position: Vec2,
+    b_mass: u32,
+) -> bool {
+    let distance = (a_position - b_position).magnitude();
+    let (radius_a, radius_b) = (mass_to_radius(a_mass), mass_to_radius(b_mass));
+    let (smaller, bigger) = (radius_a.min(radius_b), radius_a.max(radius_b));
+    math::containment_fraction(smaller, bigger, distance) >= config.eat_overlap_fraction
+}
+
+/// Whether a circle is big enough to eat a player circle or virus of `prey_mass`.
+pub fn outweighs(config: &SimConfig, predator_mass: u32, prey_mass: u32) -> bool {
+    (prey_mass as f32 / predator_mass as f32) < config.minimum_safe_mass_ratio
+}
+
+/// Mass that splits off a circle of `mass`, if it's big enough to split.
+pub fn split_mass(config: &SimConfig, mass: u32) -> Option<u32> {
+    (mass >= config.min_mass_to_split * 2).then_some(mass / 2)
+}
+
+/// Mass of a circle after one decay, circles at or below the starting mass don't decay.
+pub fn decayed_mass(config: &SimConfig, mass: u32) -> u32 {
+    if mass <= config.start_player_mass {
+        return mass;
+    }
+    (mass as f32 * config.circle_decay_factor) as u32
+}
+
+pub fn is_ready_to_recombine(config: &SimConfig, split_age_sec: f32) -> bool {
+    split_age_sec >= config.split_recombine_delay_sec
+}
+
+/// Push applied to `a`, and in reverse to `b`, when two circles that can't eat
+/// each other overlap by more than `allowed_split_circle_overlap_pct`.
+pub fn separation_push<S: Scalar>(
+    config: &SimConfig,
+    a: &Entity,
+    b: &Entity,
+) -> Option<Vector2<S>> {
+    let mut diff = Vector2::<S>::from_vec2(a.position) - Vector2::from_vec2(b.position);
+    let mut distance_sqr = diff.sqr_magnitude();
+    if distance_sqr <= S::from_f32(0.0001) {
+        diff = Vector2::new(S::ONE, S::ZERO);
+        distance_sqr = S::ONE;
+    }
+    let radius_sum = S::from_u32(a.mass).sqrt() + S::from_u32(b.mass).sqrt();
+    let radius_sum_multiplied = radius_sum * S::from_f32(config.allowed_split_circle_overlap_pct);
+    if distance_sqr < radius_sum_multiplied * radius_sum_multiplied {
+        Some(
+            diff.normalized()
+                * (radius_sum - distance_sqr.sqrt())
+                * S::from_f32(config.self_collision_speed),
+        )
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radius_is_square_root_of_mass() {
+        assert_eq!(mass_to_radius(100), 10.0);
+    }
+
+    #[test]
+    fn bigger_circles_move_slower() {
+        let config = SimConfig::default();
+        assert_eq!(
+            mass_to_max_move_speed::<f32>(&config, config.start_player_mass),
+            config.start_player_speed as f32
+        );
+        assert!(mass_to_max_move_speed::<f32>(&config, 1000) < config.start_player_speed as f32);
+    }
+
+    #[test]
+    fn overlapping_needs_enough_of_the_smaller_circle_covered() {
+        let config = SimConfig::default();
+        let origin = Vec2::new(0.0, 0.0);
+        assert!(is_overlapping(&config, origin, 100, Vec2::new(7.5, 0.0), 4));
+        assert!(is_overlapping(&config, Vec2::new(7.5, 0.0), 4, origin, 100));
+        assert!(!is_overlapping(
+            &config,
+            origin,
+            100,
+            Vec2::new(9.5, 0.0),
+            4
+        ));
+        assert!(!is_overlapping(
+            &config,
+            origin,
+            100,
+            Vec2::new(11.0, 0.0),
+            4
+        ));
+
+        let lenient = SimConfig {
+            eat_overlap_fraction: 0.5,
+            ..Default::default()
+        };
+        assert!(is_overlapping(
+            &lenient,
+            origin,
+            100,
+            Vec2::new(9.5, 0.0),
+            4
+        ));
+    }
+
+    #[test]
+    fn split_requires_twice_the_minimum_mass() {
+        let config = SimConfig::default();
+        assert_eq!(split_mass(&config, 59), None);
+        assert_eq!(split_mass(&config, 61), Some(30));
+    }
+
+    #[test]
+    fn decay_stops_at_start_mass() {
+        let config = SimConfig::default();
+        assert_eq!(decayed_mass(&config, 200), 198);
+        assert_eq!(decayed_mass(&config, config.start_player_mass), 15);
+    }
+
+    #[test]
+    fn recombine_waits_for_the_delay() {
+        let config = SimConfig::default();
+        assert!(!is_ready_to_recombine(&config, 4.9));
+        assert!(is_ready_to_recombine(&config, 5.0));
+    }
+}
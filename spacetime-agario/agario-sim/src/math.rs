@@ -0,0 +1,162 @@
+//! Circle geometry used by the eat rule.
+
+use crate::vec2::Vec2;
+use std::f32::consts::PI;
+use std::f64::consts::FRAC_PI_2;
+
+pub fn circle_area(radius: f32) -> f32 {
+    PI * radius * radius
+}
+
+/// Area of the lens where two circles with centers `distance` apart overlap.
+pub fn circle_intersection_area(radius_a: f32, radius_b: f32, distance: f32) -> f32 {
+    if distance >= radius_a + radius_b {
+        return 0.0;
+    }
+    if distance <= (radius_a - radius_b).abs() {
+        //One circle is entirely inside the other
+        return circle_area(radius_a.min(radius_b));
+    }
+    // The terms below cancel out badly in f32 when one circle is much bigger than the other
+    let (ra, rb, d) = (radius_a as f64, radius_b as f64, distance as f64);
+    let angle_a = acos(((d * d + ra * ra - rb * rb) / (2.0 * d * ra)).clamp(-1.0, 1.0));
+    let angle_b = acos(((d * d + rb * rb - ra * ra) / (2.0 * d * rb)).clamp(-1.0, 1.0));
+    let kite = 0.5
+        * ((-d + ra + rb) * (d + ra - rb) * (d - ra + rb) * (d + ra + rb))
+            .max(0.0)
+            .sqrt();
+    (ra * ra * angle_a + rb * rb * angle_b - kite) as f32
+}
+
+/// `f64::acos` comes from the platform's libm, which doesn't give the same
+/// last bit everywhere. This is fdlibm's algorithm, built only from
+/// arithmetic and `sqrt` so the eat rule agrees between the WASM module and
+/// native code. `x` must be in -1..=1.
+fn acos(x: f64) -> f64 {
+    const PIO2_LO: f64 = 6.123233995736766e-17;
+    const PS0: f64 = 0.16666666666666666;
+    const PS1: f64 = -0.3255658186224009;
+    const PS2: f64 = 0.20121253213486293;
+    const PS3: f64 = -0.04005553450067941;
+    const PS4: f64 = 0.0007915349942898145;
+    const PS5: f64 = 3.479331075960212e-05;
+    const QS1: f64 = -2.403394911734414;
+    const QS2: f64 = 2.0209457602335057;
+    const QS3: f64 = -0.6882839716054533;
+    const QS4: f64 = 0.07703815055590194;
+    let r = |z: f64| {
+        let p = z * (PS0 + z * (PS1 + z * (PS2 + z * (PS3 + z * (PS4 + z * PS5)))));
+        let q = 1.0 + z * (QS1 + z * (QS2 + z * (QS3 + z * QS4)));
+        p / q
+    };
+
+    if x >= 1.0 {
+        return 0.0;
+    }
+    if x <= -1.0 {
+        return 2.0 * FRAC_PI_2;
+    }
+    if x.abs() < 0.5 {
+        return FRAC_PI_2 - (x - (PIO2_LO - x * r(x * x)));
+    }
+    if x < 0.0 {
+        let z = (1.0 + x) * 0.5;
+        let s = z.sqrt();
+        let w = r(z) * s - PIO2_LO;
+        return 2.0 * (FRAC_PI_2 - (s + w));
+    }
+    let z = (1.0 - x) * 0.5;
+    let s = z.sqrt();
+    // s with the low word cleared, so df * df is exact
+    let df = f64::from_bits(s.to_bits() & 0xffff_ffff_0000_0000);
+    let c = (z - df * df) / (s + df);
+    let w = r(z) * s + c;
+    2.0 * (df + w)
+}
+
+/// Fraction (0..=1) of circle `a`'s area that lies inside circle `b`.
+pub fn containment_fraction(radius_a: f32, radius_b: f32, distance: f32) -> f32 {
+    let area = circle_area(radius_a);
+    if area == 0.0 {
+        return if distance <= radius_b { 1.0 } else { 0.0 };
+    }
+    (circle_intersection_area(radius_a, radius_b, distance) / area).clamp(0.0, 1.0)
+}
+
+/// Distance from `point` to the edge of the circle, negative when the point is inside.
+pub fn distance_to_edge(center: Vec2, radius: f32, point: Vec2) -> f32 {
+    (point - center).magnitude() - radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn acos_matches_std() {
+        for i in -1000..=1000 {
+            let x = i as f64 / 1000.0;
+            let (actual, expected) = (acos(x), x.acos());
+            assert!(
+                (actual - expected).abs() <= 2.0 * f64::EPSILON * expected.max(1.0),
+                "acos({x}): expected {expected}, got {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn disjoint_circles_do_not_intersect() {
+        assert_eq!(circle_intersection_area(1.0, 1.0, 2.0), 0.0);
+        assert_eq!(circle_intersection_area(3.0, 1.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn contained_circle_intersects_with_its_whole_area() {
+        assert_close(circle_intersection_area(10.0, 2.0, 5.0), 4.0 * PI);
+        assert_close(circle_intersection_area(2.0, 10.0, 0.0), 4.0 * PI);
+    }
+
+    #[test]
+    fn equal_circles_one_radius_apart() {
+        // 2π/3 - √3/2 for unit circles
+        assert_close(
+            circle_intersection_area(1.0, 1.0, 1.0),
+            2.0 * PI / 3.0 - 3f32.sqrt() / 2.0,
+        );
+    }
+
+    #[test]
+    fn intersection_is_symmetric() {
+        assert_close(
+            circle_intersection_area(3.0, 2.0, 4.0),
+            circle_intersection_area(2.0, 3.0, 4.0),
+        );
+    }
+
+    #[test]
+    fn containment_fraction_of_half_covered_circle() {
+        // A small circle centered on the edge of a huge one is almost exactly half covered
+        assert_close(containment_fraction(1.0, 10_000.0, 10_000.0), 0.5);
+        assert_close(containment_fraction(1.0, 1.0, 0.0), 1.0);
+        assert_close(
+            containment_fraction(1.0, 1.0, 1.0),
+            (2.0 * PI / 3.0 - 3f32.sqrt() / 2.0) / PI,
+        );
+        assert_eq!(containment_fraction(1.0, 1.0, 3.0), 0.0);
+    }
+
+    #[test]
+    fn distance_to_edge_is_negative_inside() {
+        let center = Vec2::new(1.0, 1.0);
+        assert_close(distance_to_edge(center, 2.0, Vec2::new(4.0, 5.0)), 3.0);
+        assert_close(distance_to_edge(center, 2.0, Vec2::new(1.0, 2.0)), -1.0);
+        assert_close(distance_to_edge(center, 2.0, Vec2::new(3.0, 1.0)), 0.0);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::vec2::FIXED_POINT_FRACTION_BITS;
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+
+/// Number type the movement math in `World` runs on: plain `f32`, or `Fixed`
+/// when `SimConfig::fixed_point_positions` is on.
+pub trait Scalar:
+    Copy
+    + Debug
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + SubAssign
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn from_u32(value: u32) -> Self;
+    /// Converts back to the `f32` positions and velocities are stored as.
+    fn to_f32(self) -> f32;
+    fn sqrt(self) -> Self;
+    fn powf(self, exponent: Self) -> Self;
+    fn sin_cos(self) -> (Self, Self);
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+}
+
+impl Scalar for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn from_u32(value: u32) -> Self {
+        value as f32
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+
+    fn powf(self, exponent: Self) -> Self {
+        f32::powf(self, exponent)
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        f32::sin_cos(self)
+    }
+
+    fn min(self, other: Self) -> Self {
+        f32::min(self, other)
+    }
+
+    fn max(self, other: Self) -> Self {
+        f32::max(self, other)
+    }
+
+    fn clamp(self, min: Self, max: Self) -> Self {
+        f32::clamp(self, min, max)
+    }
+}
+
+/// Signed fixed-point number with `Fixed::FRACTION_BITS` fractional bits.
+/// Every operation is integer math, so results are the same on every
+/// platform, unlike `f32` functions such as `powf` and `sin` that come from
+/// the platform's libm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub i64);
+
+impl Fixed {
+    /// More than the grid positions are stored on, so directions and small
+    /// forces keep their precision in between.
+    pub const FRACTION_BITS: u32 = 16;
+    const PI: Fixed = Fixed(205_887);
+    const TAU: Fixed = Fixed(411_775);
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Fixed) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: Fixed) -> Fixed {
+        let product = self.0 as i128 * other.0 as i128;
+        let half = 1 << (Fixed::FRACTION_BITS - 1);
+        Fixed(((product + half) >> Fixed::FRACTION_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    /// Dividing by zero gives zero, like `Vec2`'s division does.
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return Fixed(0);
+        }
+        Fixed((((self.0 as i128) << Fixed::FRACTION_BITS) / other.0 as i128) as i64)
+    }
+}
+
+impl Scalar for Fixed {
+    const ZERO: Self = Fixed(0);
+    const ONE: Self = Fixed(1 << Fixed::FRACTION_BITS);
+
+    fn from_f32(value: f32) -> Self {
+        // Scaling by a power of two is exact, only the rounding loses anything
+        Fixed((value as f64 * (1u64 << Fixed::FRACTION_BITS) as f64).round() as i64)
+    }
+
+    fn from_u32(value: u32) -> Self {
+        Fixed((value as i64) << Fixed::FRACTION_BITS)
+    }
+
+    /// Rounds to the `FIXED_POINT_FRACTION_BITS` grid first, which `f32`
+    /// represents exactly up to `MAX_FIXED_POINT_COORDINATE`.
+    fn to_f32(self) -> f32 {
+        let shift = Fixed::FRACTION_BITS - FIXED_POINT_FRACTION_BITS;
+        let grid_points = (self.0 + (1 << (shift - 1))) >> shift;
+        grid_points as f32 / (1 << FIXED_POINT_FRACTION_BITS) as f32
+    }
+
+    /// Rounded down, negative numbers have a root of zero.
+    fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed(0);
+        }
+        Fixed(((self.0 as u128) << Fixed::FRACTION_BITS).isqrt() as i64)
+    }
+
+    /// `self` to the power of a non-negative `exponent`. The fractional part
+    /// is built from repeated square roots: x^0.5, x^0.25, ...
+    fn powf(self, exponent: Self) -> Self {
+        let mut result = Fixed::ONE;
+        for _ in 0..exponent.0 >> Fixed::FRACTION_BITS {
+            result = result * self;
+        }
+        let mut root = self;
+        for bit in (0..Fixed::FRACTION_BITS).rev() {
+            root = root.sqrt();
+            if exponent.0 & (1 << bit) != 0 {
+                result = result * root;
+            }
+        }
+        result
+    }
+
+    /// Taylor series, after bringing the angle into -π..=π.
+    fn sin_cos(self) -> (Self, Self) {
+        let mut angle = Fixed(self.0.rem_euclid(Fixed::TAU.0));
+        if angle > Fixed::PI {
+            angle -= Fixed::TAU;
+        }
+        let angle_sqr = angle * angle;
+        let (mut sin, mut sin_term) = (angle, angle);
+        let (mut cos, mut cos_term) = (Fixed::ONE, Fixed::ONE);
+        for n in 1..10 {
+            sin_term = Fixed(-(sin_term * angle_sqr).0) / Fixed::from_u32((2 * n) * (2 * n + 1));
+            cos_term = Fixed(-(cos_term * angle_sqr).0) / Fixed::from_u32((2 * n - 1) * (2 * n));
+            sin += sin_term;
+            cos += cos_term;
+        }
+        (sin, cos)
+    }
+
+    fn min(self, other: Self) -> Self {
+        Ord::min(self, other)
+    }
+
+    fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+
+    fn clamp(self, min: Self, max: Self) -> Self {
+        Ord::clamp(self, min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Fixed, expected: f64) {
+        let actual = actual.0 as f64 / Fixed::ONE.0 as f64;
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn arithmetic_rounds_to_the_nearest_step() {
+        let third = Fixed::ONE / Fixed::from_u32(3);
+        assert_eq!(third, Fixed(21_845));
+        assert_eq!(third * Fixed::from_u32(3), Fixed(65_535));
+        assert_eq!(
+            Fixed::from_f32(2.5) * Fixed::from_f32(-1.5),
+            Fixed::from_f32(-3.75)
+        );
+        assert_eq!(Fixed::ONE / Fixed::ZERO, Fixed::ZERO);
+    }
+
+    #[test]
+    fn converts_to_the_grid() {
+        assert_eq!(Fixed::from_f32(0.1).to_f32(), 26.0 / 256.0);
+        assert_eq!(Fixed::from_f32(-1.0 / 512.0).to_f32(), 0.0);
+        let on_grid = 12_345.0 + 3.0 / 256.0;
+        assert_eq!(Fixed::from_f32(on_grid).to_f32(), on_grid);
+    }
+
+    #[test]
+    fn sqrt_and_powf() {
+        assert_eq!(Fixed::from_u32(100).sqrt(), Fixed::from_u32(10));
+        assert_close(Fixed::from_u32(2).sqrt(), std::f64::consts::SQRT_2);
+        assert_eq!(Fixed::from_f32(-4.0).sqrt(), Fixed::ZERO);
+
+        let decay = Fixed::from_f32(0.85);
+        assert_eq!(decay.powf(Fixed::ONE), decay);
+        assert_eq!(decay.powf(Fixed::ZERO), Fixed::ONE);
+        assert_close(decay.powf(Fixed::from_f32(0.74)), 0.85f64.powf(0.74));
+        assert_close(decay.powf(Fixed::from_f32(2.5)), 0.85f64.powf(2.5));
+    }
+
+    #[test]
+    fn sin_cos_matches_std() {
+        for degrees in (-720..=720).step_by(15) {
+            let angle = (degrees as f64).to_radians();
+            let (sin, cos) = Fixed::from_f32(angle as f32).sin_cos();
+            assert_close(sin, angle.sin());
+            assert_close(cos, angle.cos());
+        }
+    }
+}
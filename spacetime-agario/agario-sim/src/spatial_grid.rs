@@ -0,0 +1,43 @@
+use crate::vec2::Vec2;
+use std::collections::HashMap;
+
+/// Uniform grid used as a collision broad-phase. Every entity is bucketed by
+/// the cell its center falls in, so a query only has to look at the cells
+/// covered by the querying circle.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<u32>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, position: Vec2) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn insert(&mut self, entity_id: u32, position: Vec2) {
+        let cell = self.cell_of(position);
+        self.cells.entry(cell).or_default().push(entity_id);
+    }
+
+    /// Returns the ids of every entity whose center may lie within `radius`
+    /// of `center`. Each id is yielded at most once.
+    pub fn query(&self, center: Vec2, radius: f32) -> impl Iterator<Item = u32> + '_ {
+        let extent = Vec2::new(radius, radius);
+        let (min_x, min_y) = self.cell_of(center - extent);
+        let (max_x, max_y) = self.cell_of(center + extent);
+        (min_x..=max_x)
+            .flat_map(move |x| (min_y..=max_y).filter_map(move |y| self.cells.get(&(x, y))))
+            .flatten()
+            .copied()
+    }
+}
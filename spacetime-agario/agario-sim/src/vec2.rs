@@ -0,0 +1,143 @@
+use crate::scalar::{Fixed, Scalar};
+
+/// Fractional bits of the grid positions and velocities are stored on when
+/// `SimConfig::fixed_point_positions` is on. With 8 bits every grid point up
+/// to `MAX_FIXED_POINT_COORDINATE` is exactly representable as an `f32`.
+pub const FIXED_POINT_FRACTION_BITS: u32 = 8;
+pub const MAX_FIXED_POINT_COORDINATE: f32 =
+    (1 << (f32::MANTISSA_DIGITS - FIXED_POINT_FRACTION_BITS)) as f32;
+
+/// 2D vector over any `Scalar`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vector2<S> {
+    pub x: S,
+    pub y: S,
+}
+
+/// Plain 2D vector used by the simulation. The module's `DbVector2` converts
+/// to and from it.
+pub type Vec2 = Vector2<f32>;
+
+/// Integer-backed vector the movement math runs on when
+/// `SimConfig::fixed_point_positions` is on.
+pub type FixedVec2 = Vector2<Fixed>;
+
+impl<S: Scalar> std::ops::Add<Vector2<S>> for Vector2<S> {
+    type Output = Vector2<S>;
+
+    fn add(self, other: Vector2<S>) -> Vector2<S> {
+        Vector2 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl<S: Scalar> std::ops::AddAssign<Vector2<S>> for Vector2<S> {
+    fn add_assign(&mut self, rhs: Vector2<S>) {
+        self.x += rhs.x;
+        self.y += rhs.y;
+    }
+}
+
+impl<S: Scalar> std::iter::Sum<Vector2<S>> for Vector2<S> {
+    fn sum<I: Iterator<Item = Vector2<S>>>(iter: I) -> Self {
+        let mut r = Vector2::new(S::ZERO, S::ZERO);
+        for val in iter {
+            r += val;
+        }
+        r
+    }
+}
+
+impl<S: Scalar> std::ops::Sub<Vector2<S>> for Vector2<S> {
+    type Output = Vector2<S>;
+
+    fn sub(self, other: Vector2<S>) -> Vector2<S> {
+        Vector2 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl<S: Scalar> std::ops::SubAssign<Vector2<S>> for Vector2<S> {
+    fn sub_assign(&mut self, rhs: Vector2<S>) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+    }
+}
+
+impl<S: Scalar> std::ops::Mul<S> for Vector2<S> {
+    type Output = Vector2<S>;
+
+    fn mul(self, other: S) -> Vector2<S> {
+        Vector2 {
+            x: self.x * other,
+            y: self.y * other,
+        }
+    }
+}
+
+impl<S: Scalar> std::ops::Div<S> for Vector2<S> {
+    type Output = Vector2<S>;
+
+    fn div(self, other: S) -> Vector2<S> {
+        if other != S::ZERO {
+            Vector2 {
+                x: self.x / other,
+                y: self.y / other,
+            }
+        } else {
+            Vector2 {
+                x: S::ZERO,
+                y: S::ZERO,
+            }
+        }
+    }
+}
+
+impl<S: Scalar> Vector2<S> {
+    pub fn new(x: S, y: S) -> Self {
+        Self { x, y }
+    }
+
+    pub fn sqr_magnitude(&self) -> S {
+        self.x * self.x + self.y * self.y
+    }
+
+    pub fn magnitude(&self) -> S {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn normalized(self) -> Vector2<S> {
+        self / self.magnitude()
+    }
+
+    pub fn from_vec2(v: Vec2) -> Self {
+        Self::new(S::from_f32(v.x), S::from_f32(v.y))
+    }
+
+    /// See `Scalar::to_f32`, fixed-point vectors land on the grid.
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(self.x.to_f32(), self.y.to_f32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_vectors_normalize() {
+        let v = FixedVec2::new(Fixed::from_u32(3), Fixed::from_u32(4));
+        assert_eq!(v.magnitude(), Fixed::from_u32(5));
+        assert_eq!(
+            v.normalized().to_vec2(),
+            Vec2::new(154.0 / 256.0, 205.0 / 256.0)
+        );
+        let sum: FixedVec2 = [v, v, v].into_iter().sum();
+        assert_eq!(sum - v, v * Fixed::from_u32(2));
+        assert_eq!(v / Fixed::ZERO, FixedVec2::default());
+    }
+}
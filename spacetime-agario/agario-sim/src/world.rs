@@ -0,0 +1,1038 @@
+use crate::spatial_grid::SpatialGrid;
+use crate::{
+    is_overlapping, is_ready_to_recombine, mass_to_max_move_speed, mass_to_radius, outweighs,
+    separation_push, split_mass, Fixed, Scalar, SimConfig, Vec2, Vector2, COLLISION_GRID_CELL_SIZE,
+    REFERENCE_TICK_SEC,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// `team_id` of players that aren't on a team
+pub const NO_TEAM: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityKind {
+    Food,
+    Virus,
+    EjectedMass {
+        velocity: Vec2,
+    },
+    Circle {
+        player_id: u32,
+        direction: Vec2,
+        speed: f32,
+        /// Seconds since the circle last split off or was split from
+        split_age_sec: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entity {
+    pub entity_id: u32,
+    pub position: Vec2,
+    pub mass: u32,
+    pub kind: EntityKind,
+}
+
+impl Entity {
+    pub fn player_id(&self) -> Option<u32> {
+        match self.kind {
+            EntityKind::Circle { player_id, .. } => Some(player_id),
+            _ => None,
+        }
+    }
+}
+
+/// Movement requested by a player, `direction` is normalized and `speed` is in 0..=1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Input {
+    pub direction: Vec2,
+    pub speed: f32,
+}
+
+/// A circle that ended a step on top of something it may eat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub consumer_entity_id: u32,
+    pub consumed_entity_id: u32,
+}
+
+/// A circle that broke off `parent_entity_id` in `World::split` or `World::pop`.
+/// `piece.entity_id` is only a placeholder until the module inserts the row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Split {
+    pub parent_entity_id: u32,
+    pub piece: Entity,
+}
+
+/// Everything inside one arena.
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    pub world_size: f32,
+    pub entities: BTreeMap<u32, Entity>,
+    /// player_id -> team_id, players missing from the map are on `NO_TEAM`
+    pub player_teams: HashMap<u32, u32>,
+}
+
+impl World {
+    pub fn new(world_size: f32) -> Self {
+        Self {
+            world_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity) {
+        self.entities.insert(entity.entity_id, entity);
+    }
+
+    pub fn are_teammates(&self, a: u32, b: u32) -> bool {
+        let team_a = self.player_teams.get(&a).copied().unwrap_or(NO_TEAM);
+        team_a != NO_TEAM && Some(&team_a) == self.player_teams.get(&b)
+    }
+
+    /// player_id -> entity ids of the player's circles
+    fn circles_by_player(&self) -> BTreeMap<u32, Vec<u32>> {
+        let mut circles: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for entity in self.entities.values() {
+            if let Some(player_id) = entity.player_id() {
+                circles.entry(player_id).or_default().push(entity.entity_id);
+            }
+        }
+        circles
+    }
+
+    fn player_circle_ids(&self, player_id: u32) -> Vec<u32> {
+        self.entities
+            .values()
+            .filter(|e| e.player_id() == Some(player_id))
+            .map(|e| e.entity_id)
+            .collect()
+    }
+
+    /// Moves `mass` out of the parent circle into a new circle at `position`.
+    /// Both restart their recombine delay.
+    fn split_off(&mut self, parent_entity_id: u32, position: Vec2, mass: u32) -> Split {
+        let entity_id = self.entities.keys().next_back().map_or(1, |id| id + 1);
+        let parent = self.entities.get_mut(&parent_entity_id).unwrap();
+        parent.mass -= mass;
+        if let EntityKind::Circle { split_age_sec, .. } = &mut parent.kind {
+            *split_age_sec = 0.0;
+        }
+        let piece = Entity {
+            entity_id,
+            position,
+            mass,
+            ..*parent
+        };
+        self.insert(piece);
+        Split {
+            parent_entity_id,
+            piece,
+        }
+    }
+
+    /// Halves every circle of the player that is big enough, shooting the new
+    /// half off in the circle's direction, until the player runs out of
+    /// circle slots.
+    pub fn split(&mut self, config: &SimConfig, player_id: u32) -> Vec<Split> {
+        let circle_ids = self.player_circle_ids(player_id);
+        let mut circle_count = circle_ids.len() as u32;
+        let mut splits = Vec::new();
+        for entity_id in circle_ids {
+            if circle_count >= config.max_circles_per_player {
+                break;
+            }
+            let parent = self.entities[&entity_id];
+            let EntityKind::Circle { direction, .. } = parent.kind else {
+                continue;
+            };
+            if let Some(half_mass) = split_mass(config, parent.mass) {
+                let position = if config.fixed_point_positions {
+                    split_position::<Fixed>(parent.position, direction)
+                } else {
+                    split_position::<f32>(parent.position, direction)
+                };
+                splits.push(self.split_off(entity_id, position, half_mass));
+                circle_count += 1;
+            }
+        }
+        splits
+    }
+
+    /// Bursts a circle that swallowed a virus into as many pieces as the
+    /// player's remaining circle slots and the circle's mass allow.
+    pub fn pop(&mut self, config: &SimConfig, entity_id: u32) -> Vec<Split> {
+        let Some(circle) = self.entities.get(&entity_id).copied() else {
+            return Vec::new();
+        };
+        let Some(player_id) = circle.player_id() else {
+            return Vec::new();
+        };
+        let circle_count = self.player_circle_ids(player_id).len() as u32;
+        let pieces = config
+            .max_circles_per_player
+            .saturating_sub(circle_count)
+            .min(config.virus_pop_max_pieces)
+            .min((circle.mass / config.virus_pop_min_piece_mass).saturating_sub(1));
+        if pieces == 0 {
+            return Vec::new();
+        }
+
+        let piece_mass = circle.mass / (pieces + 1);
+        (0..pieces)
+            .map(|i| {
+                let position = if config.fixed_point_positions {
+                    pop_position::<Fixed>(circle.position, i, pieces, piece_mass)
+                } else {
+                    pop_position::<f32>(circle.position, i, pieces, piece_mass)
+                };
+                self.split_off(entity_id, position, piece_mass)
+            })
+            .collect()
+    }
+
+    /// Merges every circle of the player that is past its recombine delay
+    /// into the oldest of them. Returns the merges that were applied.
+    pub fn recombine(&mut self, config: &SimConfig, player_id: u32) -> Vec<Contact> {
+        let ready: Vec<u32> = self
+            .player_circle_ids(player_id)
+            .into_iter()
+            .filter(|id| match self.entities[id].kind {
+                EntityKind::Circle { split_age_sec, .. } => {
+                    is_ready_to_recombine(config, split_age_sec)
+                }
+                _ => false,
+            })
+            .collect();
+        let Some((&base_entity_id, rest)) = ready.split_first() else {
+            return Vec::new();
+        };
+        rest.iter()
+            .filter_map(|&entity_id| {
+                self.consume(base_entity_id, entity_id)?;
+                Some(Contact {
+                    consumer_entity_id: base_entity_id,
+                    consumed_entity_id: entity_id,
+                })
+            })
+            .collect()
+    }
+
+    /// Advances the world by `dt` seconds: applies `inputs` (keyed by
+    /// player_id), moves circles and ejected mass in sub-steps of at most
+    /// `REFERENCE_TICK_SEC`, then reports every circle that ended up on top of
+    /// something it may eat. Nothing is eaten here, pass the contacts to
+    /// `resolve_contacts` to do that.
+    pub fn step(
+        &mut self,
+        config: &SimConfig,
+        dt: f32,
+        inputs: &HashMap<u32, Input>,
+    ) -> Vec<Contact> {
+        for entity in self.entities.values_mut() {
+            if let EntityKind::Circle {
+                player_id,
+                direction,
+                speed,
+                ..
+            } = &mut entity.kind
+            {
+                if let Some(input) = inputs.get(player_id) {
+                    *direction = input.direction;
+                    *speed = input.speed;
+                }
+            }
+        }
+
+        let substeps = (dt / REFERENCE_TICK_SEC).ceil().max(1.0);
+        for _ in 0..substeps as u32 {
+            if config.fixed_point_positions {
+                self.integrate::<Fixed>(config, dt / substeps);
+            } else {
+                self.integrate::<f32>(config, dt / substeps);
+            }
+        }
+
+        self.find_contacts(config)
+    }
+
+    /// Moves everything by a single sub-step of `dt` seconds, doing the math in `S`.
+    fn integrate<S: Scalar>(&mut self, config: &SimConfig, dt: f32) {
+        // Speeds and forces are tuned per reference tick
+        let scale = S::from_f32(dt) / S::from_f32(REFERENCE_TICK_SEC);
+        let mut circle_directions: HashMap<u32, Vector2<S>> = self
+            .entities
+            .values()
+            .filter_map(|e| match e.kind {
+                EntityKind::Circle {
+                    direction, speed, ..
+                } => Some((
+                    e.entity_id,
+                    Vector2::from_vec2(direction) * S::from_f32(speed),
+                )),
+                _ => None,
+            })
+            .collect();
+        for circles in self.circles_by_player().values() {
+            self.pull_split_circles(config, circles, &mut circle_directions);
+        }
+        // Teammates can't eat each other, so their circles push apart instead
+        self.push_teammates_apart(config, &mut circle_directions);
+
+        let world_size = S::from_f32(self.world_size);
+        for entity in self.entities.values_mut() {
+            let radius = S::from_u32(entity.mass).sqrt();
+            let clamp = |position: Vector2<S>| {
+                let min = radius;
+                let max = world_size - radius;
+                Vector2::new(position.x.clamp(min, max), position.y.clamp(min, max))
+            };
+            let position = Vector2::<S>::from_vec2(entity.position);
+            match &mut entity.kind {
+                EntityKind::Circle { split_age_sec, .. } => {
+                    let direction = circle_directions[&entity.entity_id];
+                    entity.position = clamp(
+                        position
+                            + direction * mass_to_max_move_speed::<S>(config, entity.mass) * scale,
+                    )
+                    .to_vec2();
+                    *split_age_sec += dt;
+                }
+                EntityKind::EjectedMass { velocity } => {
+                    if velocity.sqr_magnitude() == 0.0 {
+                        continue;
+                    }
+                    let mut new_velocity = Vector2::<S>::from_vec2(*velocity);
+                    entity.position = clamp(position + new_velocity * scale).to_vec2();
+                    new_velocity =
+                        new_velocity * S::from_f32(config.eject_velocity_decay).powf(scale);
+                    if new_velocity.sqr_magnitude() < S::from_f32(0.01) {
+                        new_velocity = Vector2::new(S::ZERO, S::ZERO);
+                    }
+                    *velocity = new_velocity.to_vec2();
+                }
+                EntityKind::Food | EntityKind::Virus => {}
+            }
+        }
+    }
+
+    /// Gravitates a player's circles towards each other shortly before they
+    /// recombine and keeps them from overlapping until then.
+    fn pull_split_circles<S: Scalar>(
+        &self,
+        config: &SimConfig,
+        circles: &[u32],
+        circle_directions: &mut HashMap<u32, Vector2<S>>,
+    ) {
+        if circles.len() <= 1 {
+            return;
+        }
+        let count = circles.len();
+
+        for entity_i in circles.iter().map(|id| &self.entities[id]) {
+            let EntityKind::Circle { split_age_sec, .. } = entity_i.kind else {
+                continue;
+            };
+            let time_before_recombining = (S::from_f32(config.split_recombine_delay_sec)
+                - S::from_f32(split_age_sec))
+            .max(S::ZERO);
+            let grav_pull_sec = S::from_f32(config.split_grav_pull_before_recombine_sec);
+            if time_before_recombining > grav_pull_sec {
+                continue;
+            }
+
+            for entity_j in circles.iter().map(|id| &self.entities[id]) {
+                if entity_j.entity_id == entity_i.entity_id {
+                    continue;
+                }
+                let mut diff = Vector2::<S>::from_vec2(entity_i.position)
+                    - Vector2::from_vec2(entity_j.position);
+                let mut distance_sqr = diff.sqr_magnitude();
+                if distance_sqr <= S::from_f32(0.0001) {
+                    diff = Vector2::new(S::ONE, S::ZERO);
+                    distance_sqr = S::ONE;
+                }
+                let radius_sum =
+                    S::from_u32(entity_i.mass).sqrt() + S::from_u32(entity_j.mass).sqrt();
+                if distance_sqr > radius_sum * radius_sum {
+                    let gravity_multiplier = S::ONE - time_before_recombining / grav_pull_sec;
+                    let vec = diff.normalized()
+                        * (radius_sum - distance_sqr.sqrt())
+                        * gravity_multiplier
+                        * S::from_f32(0.05)
+                        / S::from_u32(count as u32);
+                    let half = vec / S::from_u32(2);
+                    *circle_directions.get_mut(&entity_i.entity_id).unwrap() += half;
+                    *circle_directions.get_mut(&entity_j.entity_id).unwrap() -= half;
+                }
+            }
+        }
+
+        // Force circles apart
+        for (i, id_i) in circles.iter().enumerate() {
+            let entity_i = &self.entities[id_i];
+            for id_j in &circles[i + 1..] {
+                let entity_j = &self.entities[id_j];
+                if let Some(vec) = separation_push::<S>(config, entity_i, entity_j) {
+                    let half = vec / S::from_u32(2);
+                    *circle_directions.get_mut(id_i).unwrap() += half;
+                    *circle_directions.get_mut(id_j).unwrap() -= half;
+                }
+            }
+        }
+    }
+
+    fn push_teammates_apart<S: Scalar>(
+        &self,
+        config: &SimConfig,
+        circle_directions: &mut HashMap<u32, Vector2<S>>,
+    ) {
+        // entity_id -> (team_id, player_id)
+        let team_circles: HashMap<u32, (u32, u32)> = self
+            .entities
+            .values()
+            .filter_map(|e| {
+                let player_id = e.player_id()?;
+                let team_id = *self.player_teams.get(&player_id)?;
+                (team_id != NO_TEAM).then_some((e.entity_id, (team_id, player_id)))
+            })
+            .collect();
+        if team_circles.is_empty() {
+            return;
+        }
+
+        let max_radius = team_circles
+            .keys()
+            .map(|id| mass_to_radius(self.entities[id].mass))
+            .fold(0.0, f32::max);
+        let mut grid = SpatialGrid::new(COLLISION_GRID_CELL_SIZE);
+        for entity_id in team_circles.keys() {
+            grid.insert(*entity_id, self.entities[entity_id].position);
+        }
+        for (entity_id, (team_i, player_i)) in team_circles.iter() {
+            let entity_i = &self.entities[entity_id];
+            let search_radius = mass_to_radius(entity_i.mass) + max_radius;
+            for other_id in grid.query(entity_i.position, search_radius) {
+                // Visit each pair once
+                if other_id <= *entity_id {
+                    continue;
+                }
+                let (team_j, player_j) = &team_circles[&other_id];
+                if team_i != team_j || player_i == player_j {
+                    continue;
+                }
+                if let Some(vec) = separation_push::<S>(config, entity_i, &self.entities[&other_id])
+                {
+                    let half = vec / S::from_u32(2);
+                    *circle_directions.get_mut(entity_id).unwrap() += half;
+                    *circle_directions.get_mut(&other_id).unwrap() -= half;
+                }
+            }
+        }
+    }
+
+    fn find_contacts(&self, config: &SimConfig) -> Vec<Contact> {
+        let mut grid = SpatialGrid::new(COLLISION_GRID_CELL_SIZE);
+        for entity in self.entities.values() {
+            grid.insert(entity.entity_id, entity.position);
+        }
+
+        let mut contacts = Vec::new();
+        for circle in self.entities.values() {
+            let Some(player_id) = circle.player_id() else {
+                continue;
+            };
+            // A circle can only eat entities whose center lies inside its own radius,
+            // so there's no need to look any further than that.
+            for other_id in grid.query(circle.position, mass_to_radius(circle.mass)) {
+                let other = &self.entities[&other_id];
+                if other.entity_id == circle.entity_id
+                    || !is_overlapping(
+                        config,
+                        circle.position,
+                        circle.mass,
+                        other.position,
+                        other.mass,
+                    )
+                {
+                    continue;
+                }
+                let edible = match other.kind {
+                    EntityKind::Circle {
+                        player_id: other_player_id,
+                        ..
+                    } => {
+                        other_player_id != player_id
+                            && !self.are_teammates(player_id, other_player_id)
+                            && outweighs(config, circle.mass, other.mass)
+                    }
+                    // Circles that are too small to swallow the virus can hide behind it
+                    EntityKind::Virus => outweighs(config, circle.mass, other.mass),
+                    EntityKind::Food | EntityKind::EjectedMass { .. } => true,
+                };
+                if edible {
+                    contacts.push(Contact {
+                        consumer_entity_id: circle.entity_id,
+                        consumed_entity_id: other_id,
+                    });
+                }
+            }
+        }
+        contacts
+    }
+
+    /// Eats the contacts from `step` in a fixed order: largest consumer first,
+    /// ties broken by entity id. Each entity is eaten at most once and a
+    /// circle that got eaten doesn't get to eat anything itself. Returns the
+    /// contacts that were applied, in the order they were applied.
+    pub fn resolve_contacts(&mut self, mut contacts: Vec<Contact>) -> Vec<Contact> {
+        let mass = |entity_id: u32| self.entities.get(&entity_id).map_or(0, |e| e.mass);
+        contacts.sort_by_key(|c| {
+            (
+                std::cmp::Reverse(mass(c.consumer_entity_id)),
+                c.consumer_entity_id,
+                std::cmp::Reverse(mass(c.consumed_entity_id)),
+                c.consumed_entity_id,
+            )
+        });
+        contacts.dedup();
+        contacts
+            .into_iter()
+            .filter(|c| {
+                self.consume(c.consumer_entity_id, c.consumed_entity_id)
+                    .is_some()
+            })
+            .collect()
+    }
+
+    /// Moves the consumed entity's mass into the consumer and removes it.
+    /// Returns the removed entity, or `None` if either one is already gone.
+    pub fn consume(&mut self, consumer_entity_id: u32, consumed_entity_id: u32) -> Option<Entity> {
+        if consumer_entity_id == consumed_entity_id
+            || !self.entities.contains_key(&consumer_entity_id)
+        {
+            return None;
+        }
+        let consumed = self.entities.remove(&consumed_entity_id)?;
+        self.entities.get_mut(&consumer_entity_id)?.mass += consumed.mass;
+        Some(consumed)
+    }
+}
+
+/// Where `World::split` puts the half that splits off.
+fn split_position<S: Scalar>(position: Vec2, direction: Vec2) -> Vec2 {
+    (Vector2::<S>::from_vec2(position) + Vector2::from_vec2(direction)).to_vec2()
+}
+
+/// Where `World::pop` puts piece `i` of `pieces`, spread evenly around the circle.
+fn pop_position<S: Scalar>(center: Vec2, i: u32, pieces: u32, piece_mass: u32) -> Vec2 {
+    let angle = S::from_f32(std::f32::consts::TAU) * S::from_u32(i) / S::from_u32(pieces);
+    let (sin, cos) = angle.sin_cos();
+    let offset = Vector2::new(cos, sin) * S::from_u32(piece_mass).sqrt();
+    (Vector2::<S>::from_vec2(center) + offset).to_vec2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle(entity_id: u32, player_id: u32, x: f32, y: f32, mass: u32) -> Entity {
+        Entity {
+            entity_id,
+            position: Vec2::new(x, y),
+            mass,
+            kind: EntityKind::Circle {
+                player_id,
+                direction: Vec2::new(0.0, 0.0),
+                speed: 0.0,
+                split_age_sec: 100.0,
+            },
+        }
+    }
+
+    fn food(entity_id: u32, x: f32, y: f32) -> Entity {
+        Entity {
+            entity_id,
+            position: Vec2::new(x, y),
+            mass: 3,
+            kind: EntityKind::Food,
+        }
+    }
+
+    fn idle_step(world: &mut World, config: &SimConfig) -> Vec<Contact> {
+        world.step(config, 0.05, &HashMap::new())
+    }
+
+    fn contact(consumer_entity_id: u32, consumed_entity_id: u32) -> Contact {
+        Contact {
+            consumer_entity_id,
+            consumed_entity_id,
+        }
+    }
+
+    #[test]
+    fn circle_eats_food_under_it() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 100));
+        world.insert(food(2, 505.0, 500.0));
+        world.insert(food(3, 520.0, 500.0));
+
+        assert_eq!(idle_step(&mut world, &config), vec![contact(1, 2)]);
+        assert!(world.consume(1, 2).is_some());
+        assert_eq!(world.entities[&1].mass, 103);
+        assert!(!world.entities.contains_key(&2));
+        assert!(world.consume(1, 2).is_none());
+    }
+
+    #[test]
+    fn contested_food_goes_to_the_largest_circle() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 100));
+        world.insert(circle(2, 2, 515.0, 500.0, 110));
+        world.insert(food(3, 507.0, 500.0));
+
+        let contacts = idle_step(&mut world, &config);
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(world.resolve_contacts(contacts), vec![contact(2, 3)]);
+        assert_eq!(world.entities[&1].mass, 100);
+        assert_eq!(world.entities[&2].mass, 113);
+    }
+
+    #[test]
+    fn eaten_circles_cannot_eat_in_the_same_tick() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(food(1, 504.0, 500.0));
+        world.insert(circle(2, 2, 500.0, 500.0, 50));
+        world.insert(circle(3, 3, 503.0, 500.0, 400));
+
+        let contacts = idle_step(&mut world, &config);
+        assert_eq!(
+            world.resolve_contacts(contacts),
+            vec![contact(3, 2), contact(3, 1)]
+        );
+        assert_eq!(world.entities[&3].mass, 453);
+        assert_eq!(world.entities.len(), 1);
+    }
+
+    #[test]
+    fn bigger_circle_eats_smaller_enemy() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 100));
+        world.insert(circle(2, 2, 505.0, 500.0, 50));
+
+        assert_eq!(idle_step(&mut world, &config), vec![contact(1, 2)]);
+    }
+
+    #[test]
+    fn similar_sizes_cannot_eat_each_other() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 100));
+        world.insert(circle(2, 2, 505.0, 500.0, 90));
+
+        assert!(idle_step(&mut world, &config).is_empty());
+    }
+
+    #[test]
+    fn teammates_and_own_circles_are_not_eaten() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 100));
+        world.insert(circle(2, 1, 505.0, 500.0, 20));
+        world.insert(circle(3, 2, 500.0, 505.0, 20));
+        world.player_teams.insert(1, 7);
+        world.player_teams.insert(2, 7);
+
+        assert!(idle_step(&mut world, &config).is_empty());
+    }
+
+    #[test]
+    fn small_circles_hide_behind_viruses() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(Entity {
+            entity_id: 1,
+            position: Vec2::new(500.0, 500.0),
+            mass: 100,
+            kind: EntityKind::Virus,
+        });
+        world.insert(circle(2, 1, 502.0, 500.0, 60));
+        world.insert(circle(3, 2, 300.0, 300.0, 200));
+        world.insert(Entity {
+            entity_id: 4,
+            position: Vec2::new(302.0, 300.0),
+            mass: 100,
+            kind: EntityKind::Virus,
+        });
+
+        assert_eq!(idle_step(&mut world, &config), vec![contact(3, 4)]);
+    }
+
+    #[test]
+    fn input_moves_circle_at_max_speed() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, config.start_player_mass));
+        let inputs = HashMap::from([(
+            1,
+            Input {
+                direction: Vec2::new(1.0, 0.0),
+                speed: 1.0,
+            },
+        )]);
+
+        world.step(&config, 0.05, &inputs);
+        let position = world.entities[&1].position;
+        assert_eq!(position, Vec2::new(510.0, 500.0));
+    }
+
+    #[test]
+    fn motion_scales_with_elapsed_time() {
+        let config = SimConfig::default();
+        let inputs = HashMap::from([(
+            1,
+            Input {
+                direction: Vec2::new(1.0, 0.0),
+                speed: 1.0,
+            },
+        )]);
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, config.start_player_mass));
+        let mut late_world = world.clone();
+
+        world.step(&config, 0.025, &inputs);
+        world.step(&config, 0.025, &inputs);
+        assert_eq!(world.entities[&1].position, Vec2::new(510.0, 500.0));
+
+        // A late tick catches up in reference sized sub-steps
+        late_world.step(&config, 0.15, &inputs);
+        assert_eq!(late_world.entities[&1].position, Vec2::new(530.0, 500.0));
+    }
+
+    #[test]
+    fn fixed_point_positions_stay_on_the_grid() {
+        let config = SimConfig {
+            fixed_point_positions: true,
+            ..Default::default()
+        };
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 37));
+        world.insert(Entity {
+            entity_id: 2,
+            position: Vec2::new(100.0, 100.0),
+            mass: 4,
+            kind: EntityKind::EjectedMass {
+                velocity: Vec2::new(3.3, 1.7),
+            },
+        });
+        let inputs = HashMap::from([(
+            1,
+            Input {
+                direction: Vec2::new(0.3, 0.7).normalized(),
+                speed: 0.9,
+            },
+        )]);
+        let on_grid = |v: f32| (v * 256.0).fract() == 0.0;
+        for _ in 0..20 {
+            world.step(&config, 0.037, &inputs);
+            for entity in world.entities.values() {
+                assert!(on_grid(entity.position.x) && on_grid(entity.position.y));
+            }
+        }
+    }
+
+    /// One tick of the recorded input stream the replay below plays back
+    struct RecordedTick {
+        dt: f32,
+        player_id: u32,
+        input: Input,
+        split: bool,
+    }
+
+    const fn recorded_tick(
+        dt: f32,
+        player_id: u32,
+        x: f32,
+        y: f32,
+        speed: f32,
+        split: bool,
+    ) -> RecordedTick {
+        RecordedTick {
+            dt,
+            player_id,
+            input: Input {
+                direction: Vec2 { x, y },
+                speed,
+            },
+            split,
+        }
+    }
+
+    const RECORDED_INPUTS: [RecordedTick; 8] = [
+        recorded_tick(0.05, 1, 0.991_77, 0.128_02, 1.0, false),
+        recorded_tick(0.037, 2, -0.6, -0.8, 0.63, false),
+        recorded_tick(0.061, 1, 0.999_5, -0.031_6, 0.9, true),
+        recorded_tick(0.12, 3, 0.8, 0.6, 1.0, false),
+        recorded_tick(0.05, 2, 0.28, 0.96, 0.2, false),
+        recorded_tick(0.043, 1, 0.970_1, 0.242_5, 1.0, false),
+        recorded_tick(0.05, 3, -1.0, 0.0, 0.75, false),
+        recorded_tick(0.2, 1, 0.894_4, -0.447_2, 0.5, false),
+    ];
+
+    /// Replays `RECORDED_INPUTS` with splits, a virus pop and recombining,
+    /// and hashes every entity at the end.
+    fn replay_recording(config: &SimConfig) -> u64 {
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 300.0, 300.0, 400));
+        world.insert(circle(2, 2, 700.0, 700.0, 120));
+        world.insert(circle(3, 3, 715.0, 700.0, 100));
+        world.player_teams.insert(2, 5);
+        world.player_teams.insert(3, 5);
+        world.insert(Entity {
+            entity_id: 4,
+            position: Vec2::new(430.0, 302.0),
+            mass: 100,
+            kind: EntityKind::Virus,
+        });
+        world.insert(Entity {
+            entity_id: 5,
+            position: Vec2::new(500.0, 500.0),
+            mass: 12,
+            kind: EntityKind::EjectedMass {
+                velocity: Vec2::new(7.3, -2.1),
+            },
+        });
+        for i in 0..20 {
+            world.insert(food(10 + i, 320.0 + 9.7 * i as f32, 304.0 + 1.3 * i as f32));
+        }
+
+        let mut pops = 0;
+        for tick in 0..160 {
+            let recorded = &RECORDED_INPUTS[tick % RECORDED_INPUTS.len()];
+            // Only split on the first lap, or the circles get too small to pop on the virus
+            if recorded.split && tick < RECORDED_INPUTS.len() {
+                world.split(config, recorded.player_id);
+            }
+            let inputs = HashMap::from([(recorded.player_id, recorded.input)]);
+            let before = world.entities.clone();
+            let contacts = world.step(config, recorded.dt, &inputs);
+            for eaten in world.resolve_contacts(contacts) {
+                if before[&eaten.consumed_entity_id].kind == EntityKind::Virus {
+                    pops += world.pop(config, eaten.consumer_entity_id).len();
+                }
+            }
+            for player_id in 1..=3 {
+                world.recombine(config, player_id);
+            }
+        }
+        assert!(pops > 0, "the recording should pop a circle on the virus");
+
+        world
+            .entities
+            .values()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, entity| {
+                let velocity = match entity.kind {
+                    EntityKind::EjectedMass { velocity } => velocity,
+                    _ => Vec2::default(),
+                };
+                [
+                    entity.entity_id,
+                    entity.mass,
+                    entity.position.x.to_bits(),
+                    entity.position.y.to_bits(),
+                    velocity.x.to_bits(),
+                    velocity.y.to_bits(),
+                ]
+                .into_iter()
+                .fold(hash, |hash, word| {
+                    (hash ^ word as u64).wrapping_mul(0x100_0000_01b3)
+                })
+            })
+    }
+
+    #[test]
+    fn fixed_point_replay_matches_the_recording() {
+        let config = SimConfig {
+            fixed_point_positions: true,
+            ..Default::default()
+        };
+        // Recorded from an earlier run, any platform or float change to the
+        // movement math shows up as a different hash
+        assert_eq!(replay_recording(&config), 17_938_327_769_026_641_033);
+    }
+
+    #[test]
+    fn circles_are_clamped_to_the_border() {
+        let config = SimConfig::default();
+        let mut world = World::new(100.0);
+        world.insert(circle(1, 1, 95.0, 5.0, 25));
+        let inputs = HashMap::from([(
+            1,
+            Input {
+                direction: Vec2::new(1.0, -1.0).normalized(),
+                speed: 1.0,
+            },
+        )]);
+
+        world.step(&config, 0.05, &inputs);
+        assert_eq!(world.entities[&1].position, Vec2::new(95.0, 5.0));
+    }
+
+    #[test]
+    fn ejected_mass_slows_down_and_stops_at_the_border() {
+        let config = SimConfig::default();
+        let mut world = World::new(100.0);
+        world.insert(Entity {
+            entity_id: 1,
+            position: Vec2::new(80.0, 50.0),
+            mass: 4,
+            kind: EntityKind::EjectedMass {
+                velocity: Vec2::new(20.0, 0.0),
+            },
+        });
+
+        idle_step(&mut world, &config);
+        let ejected = world.entities[&1];
+        assert_eq!(ejected.position, Vec2::new(98.0, 50.0));
+        assert_eq!(
+            ejected.kind,
+            EntityKind::EjectedMass {
+                velocity: Vec2::new(17.0, 0.0)
+            }
+        );
+
+        for _ in 0..50 {
+            idle_step(&mut world, &config);
+        }
+        assert_eq!(
+            world.entities[&1].kind,
+            EntityKind::EjectedMass {
+                velocity: Vec2::new(0.0, 0.0)
+            }
+        );
+    }
+
+    #[test]
+    fn fresh_split_circles_push_apart_until_ready_to_recombine() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        for (entity_id, x) in [(1, 500.0), (2, 501.0)] {
+            world.insert(Entity {
+                kind: EntityKind::Circle {
+                    player_id: 1,
+                    direction: Vec2::new(0.0, 0.0),
+                    speed: 0.0,
+                    split_age_sec: 0.0,
+                },
+                ..circle(entity_id, 1, x, 500.0, 100)
+            });
+        }
+
+        let start_distance =
+            (world.entities[&1].position - world.entities[&2].position).magnitude();
+        idle_step(&mut world, &config);
+        let distance = (world.entities[&1].position - world.entities[&2].position).magnitude();
+        assert!(distance > start_distance);
+
+        let ticks = (config.split_recombine_delay_sec / 0.05) as usize + 1;
+        for _ in 0..ticks {
+            idle_step(&mut world, &config);
+        }
+        let EntityKind::Circle { split_age_sec, .. } = world.entities[&1].kind else {
+            unreachable!();
+        };
+        assert!(crate::is_ready_to_recombine(&config, split_age_sec));
+    }
+
+    fn player_mass(world: &World, player_id: u32) -> u32 {
+        world
+            .entities
+            .values()
+            .filter(|e| e.player_id() == Some(player_id))
+            .map(|e| e.mass)
+            .sum()
+    }
+
+    #[test]
+    fn split_halves_circles_until_the_cap() {
+        let config = SimConfig {
+            max_circles_per_player: 3,
+            ..Default::default()
+        };
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 201));
+        world.insert(food(2, 100.0, 100.0));
+
+        let splits = world.split(&config, 1);
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].parent_entity_id, 1);
+        assert_eq!(splits[0].piece.mass, 100);
+        assert_eq!(world.entities[&1].mass, 101);
+        assert_eq!(world.entities[&splits[0].piece.entity_id], splits[0].piece);
+        assert_eq!(player_mass(&world, 1), 201);
+
+        // Both circles could split again, but only one slot is left
+        assert_eq!(world.split(&config, 1).len(), 1);
+        assert_eq!(world.player_circle_ids(1).len(), 3);
+        assert!(world.split(&config, 1).is_empty());
+        assert_eq!(player_mass(&world, 1), 201);
+    }
+
+    #[test]
+    fn small_circles_do_not_split() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, config.min_mass_to_split * 2 - 1));
+
+        assert!(world.split(&config, 1).is_empty());
+        assert_eq!(world.entities.len(), 1);
+    }
+
+    #[test]
+    fn pop_bursts_into_the_free_slots() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 200));
+
+        let splits = world.pop(&config, 1);
+        assert_eq!(splits.len(), config.virus_pop_max_pieces as usize);
+        assert!(splits.iter().all(|s| s.piece.mass == 200 / 9));
+        assert_eq!(player_mass(&world, 1), 200);
+
+        // 9 of the 16 slots are taken now
+        world.insert(circle(20, 1, 300.0, 300.0, 300));
+        assert_eq!(world.pop(&config, 20).len(), 6);
+        assert_eq!(
+            world.player_circle_ids(1).len(),
+            config.max_circles_per_player as usize
+        );
+        assert!(world.pop(&config, 20).is_empty());
+        assert_eq!(player_mass(&world, 1), 500);
+    }
+
+    #[test]
+    fn split_circles_merge_after_the_delay() {
+        let config = SimConfig::default();
+        let mut world = World::new(1000.0);
+        world.insert(circle(1, 1, 500.0, 500.0, 200));
+        world.split(&config, 1);
+        world.split(&config, 1);
+        assert_eq!(world.player_circle_ids(1).len(), 4);
+
+        assert!(world.recombine(&config, 1).is_empty());
+
+        let ticks = (config.split_recombine_delay_sec / 0.05) as usize + 1;
+        for _ in 0..ticks {
+            idle_step(&mut world, &config);
+        }
+        let merges = world.recombine(&config, 1);
+        assert_eq!(merges.len(), 3);
+        assert!(merges.iter().all(|m| m.consumer_entity_id == 1));
+        assert_eq!(world.player_circle_ids(1), vec![1]);
+        assert_eq!(world.entities[&1].mass, 200);
+    }
+}
@@ -1,60 +1,491 @@
 pub mod math;
 
+use agario_sim::{
+    decayed_mass, mass_to_radius, split_mass, EntityKind, Input, SimConfig, Split, World,
+    MAX_FIXED_POINT_COORDINATE,
+};
 use math::DbVector2;
 use spacetimedb::rand::Rng;
-use spacetimedb::{spacetimedb_lib::ScheduleAt, Identity, ReducerContext, Table, Timestamp, TimeDuration};
-use std::{collections::HashMap, time::Duration};
+use spacetimedb::{
+    spacetimedb_lib::ScheduleAt, Identity, ReducerContext, SpacetimeType, Table, TimeDuration,
+    Timestamp, ViewContext,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 // TODO:
 // - [x] Remove players when they are eaten on the client + death + respawn screen
 // - [x] Player splitting + increased area of view
 // - [x] Overlap amount should be more significant in order to eat
-// - [ ] Viruses
-// - [ ] Ejecting mass
-// - [ ] Leaderboard
-
-const START_PLAYER_MASS: u32 = 15;
-const START_PLAYER_SPEED: u32 = 10;
-const FOOD_MASS_MIN: u32 = 2;
-const FOOD_MASS_MAX: u32 = 4;
-const TARGET_FOOD_COUNT: usize = 600;
-const MINIMUM_SAFE_MASS_RATIO: f32 = 0.85;
-
-const MIN_MASS_TO_SPLIT: u32 = START_PLAYER_MASS * 2;
-const MAX_CIRCLES_PER_PLAYER: u32 = 16;
-const SPLIT_RECOMBINE_DELAY_SEC: f32 = 5.0;
-const SPLIT_GRAV_PULL_BEFORE_RECOMBINE_SEC: f32 = 2.0;
-const ALLOWED_SPLIT_CIRCLE_OVERLAP_PCT: f32 = 0.9;
-const SELF_COLLISION_SPEED: f32 = 0.05; //1 == instantly separate circles. less means separation takes time
-
+// - [x] Viruses
+// - [x] Ejecting mass
+// - [x] Leaderboard
+
+/// Game rules. There is a single row with `id: 0`, seeded from
+/// `Config::default()` in `init` and changed at runtime through `update_config`.
+/// `world_size`, `max_players_per_arena`, `target_food_count` and
+/// `target_virus_count` are only the defaults for newly created arenas.
 #[spacetimedb::table(name = config, public)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     #[primary_key]
     pub id: u32,
     pub world_size: u64,
+    pub max_players_per_arena: u32,
+
+    pub start_player_mass: u32,
+    pub start_player_speed: u32,
+    pub food_mass_min: u32,
+    pub food_mass_max: u32,
+    pub target_food_count: u32,
+    pub minimum_safe_mass_ratio: f32,
+    pub eat_overlap_fraction: f32, //Share of the smaller circle that must be covered to eat it, 0.5..=1
+    pub circle_decay_factor: f32,
+
+    pub min_mass_to_split: u32,
+    pub max_circles_per_player: u32,
+    pub split_recombine_delay_sec: f32,
+    pub split_grav_pull_before_recombine_sec: f32,
+    pub allowed_split_circle_overlap_pct: f32,
+    pub self_collision_speed: f32, //1 == instantly separate circles. less means separation takes time
+
+    pub target_virus_count: u32,
+    pub virus_mass: u32,
+    pub virus_pop_max_pieces: u32,
+    pub virus_pop_min_piece_mass: u32,
+
+    pub min_mass_to_eject: u32,
+    pub ejected_mass: u32,
+    pub eject_speed: f32,
+    pub eject_velocity_decay: f32,
+    pub eject_cooldown_ms: u64,
+
+    pub leaderboard_size: u32,
+    pub kill_event_retention_sec: u64,
+    pub round_duration_sec: u64, //0 == rounds disabled, the world runs forever. Opt in through update_config
+    pub round_result_count: u32,
+    pub chat_max_length: u32,
+    pub chat_cooldown_ms: u64,
+    pub chat_message_retention_sec: u64,
+    pub max_player_inputs_per_sec: u32, //Clients calling update_player_input more often get sent back to the lobby
+    pub input_abuse_block_sec: u64,     //How long a kicked client has to wait before entering again
+    pub bots_per_arena: u32,            //Each human in an arena replaces one bot
+    pub bot_view_distance: f32,
+    pub max_tick_dt_ms: u64, //Longer gaps between ticks are cut short instead of teleporting circles
+    pub fixed_point_positions: bool, //Run movement on integer FixedVec2 math so recorded inputs replay identically
+    pub view_radius_base: f32,
+    pub view_radius_per_sqrt_mass: f32,
+    pub view_radius_per_extra_circle: f32,
+    pub chunk_size: u32,        //Side of the grid cells behind `Entity::chunk_id`
+    pub visibility_margin: f32, //Added to the view radius when filling `visible_entity`
+
+    pub move_all_players_interval_ms: u64,
+    pub spawn_food_interval_ms: u64,
+    pub spawn_virus_interval_ms: u64,
+    pub circle_decay_interval_ms: u64,
+    pub update_leaderboard_interval_ms: u64,
+    pub trim_kill_events_interval_ms: u64,
+    pub trim_chat_messages_interval_ms: u64,
+    pub update_bots_interval_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            world_size: 1000,
+            max_players_per_arena: 50,
+
+            start_player_mass: 15,
+            start_player_speed: 10,
+            food_mass_min: 2,
+            food_mass_max: 4,
+            target_food_count: 600,
+            minimum_safe_mass_ratio: 0.85,
+            eat_overlap_fraction: 0.75,
+            circle_decay_factor: 0.99,
+
+            min_mass_to_split: 30,
+            max_circles_per_player: 16,
+            split_recombine_delay_sec: 5.0,
+            split_grav_pull_before_recombine_sec: 2.0,
+            allowed_split_circle_overlap_pct: 0.9,
+            self_collision_speed: 0.05,
+
+            target_virus_count: 12,
+            virus_mass: 100,
+            virus_pop_max_pieces: 8,
+            virus_pop_min_piece_mass: 15,
+
+            min_mass_to_eject: 32,
+            ejected_mass: 12,
+            eject_speed: 20.0,
+            eject_velocity_decay: 0.85,
+            eject_cooldown_ms: 100,
+
+            leaderboard_size: 10,
+            kill_event_retention_sec: 600,
+            round_duration_sec: 0,
+            round_result_count: 3,
+            chat_max_length: 200,
+            chat_cooldown_ms: 1000,
+            chat_message_retention_sec: 300,
+            max_player_inputs_per_sec: 1000, //The frontend sends one per mousemove, so stay well above frame rates
+            input_abuse_block_sec: 30,
+            bots_per_arena: 8,
+            bot_view_distance: 200.0,
+            max_tick_dt_ms: 250,
+            fixed_point_positions: false,
+            view_radius_base: 100.0,
+            view_radius_per_sqrt_mass: 8.0,
+            view_radius_per_extra_circle: 20.0,
+            chunk_size: 250,
+            visibility_margin: 50.0,
+
+            move_all_players_interval_ms: 50,
+            spawn_food_interval_ms: 500,
+            spawn_virus_interval_ms: 5000,
+            circle_decay_interval_ms: 5000,
+            update_leaderboard_interval_ms: 1000,
+            trim_kill_events_interval_ms: 60_000,
+            trim_chat_messages_interval_ms: 30_000,
+            update_bots_interval_ms: 100,
+        }
+    }
 }
 
-#[spacetimedb::table(name = entity, public)]
+impl Config {
+    fn sim_config(&self) -> SimConfig {
+        SimConfig {
+            start_player_mass: self.start_player_mass,
+            start_player_speed: self.start_player_speed,
+            minimum_safe_mass_ratio: self.minimum_safe_mass_ratio,
+            eat_overlap_fraction: self.eat_overlap_fraction,
+            circle_decay_factor: self.circle_decay_factor,
+            min_mass_to_split: self.min_mass_to_split,
+            max_circles_per_player: self.max_circles_per_player,
+            virus_pop_max_pieces: self.virus_pop_max_pieces,
+            virus_pop_min_piece_mass: self.virus_pop_min_piece_mass,
+            split_recombine_delay_sec: self.split_recombine_delay_sec,
+            split_grav_pull_before_recombine_sec: self.split_grav_pull_before_recombine_sec,
+            allowed_split_circle_overlap_pct: self.allowed_split_circle_overlap_pct,
+            self_collision_speed: self.self_collision_speed,
+            eject_velocity_decay: self.eject_velocity_decay,
+            fixed_point_positions: self.fixed_point_positions,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.world_size == 0 || self.max_players_per_arena == 0 {
+            return Err("world_size and max_players_per_arena must be positive".into());
+        }
+        if self.start_player_mass == 0 || self.start_player_speed == 0 {
+            return Err("Starting mass and speed must be positive".into());
+        }
+        if self.food_mass_min == 0 || self.food_mass_min >= self.food_mass_max {
+            return Err("food_mass_min must be positive and below food_mass_max".into());
+        }
+        if self.max_circles_per_player == 0 || self.virus_pop_min_piece_mass == 0 {
+            return Err("Circle limits must be positive".into());
+        }
+        if self.ejected_mass == 0 || self.ejected_mass >= self.min_mass_to_eject {
+            return Err("ejected_mass must be positive and below min_mass_to_eject".into());
+        }
+        if self.chat_max_length == 0 || self.max_player_inputs_per_sec == 0 {
+            return Err("Chat and input limits must be positive".into());
+        }
+        if self.max_tick_dt_ms == 0 {
+            return Err("max_tick_dt_ms must be positive".into());
+        }
+        if self.chunk_size == 0 {
+            return Err("chunk_size must be positive".into());
+        }
+        self.validate_world_size(self.world_size)?;
+        let fractions = [
+            self.minimum_safe_mass_ratio,
+            self.circle_decay_factor,
+            self.allowed_split_circle_overlap_pct,
+            self.self_collision_speed,
+            self.eject_velocity_decay,
+        ];
+        if fractions.iter().any(|f| !(0.0..=1.0).contains(f)) {
+            return Err("Ratios, factors and percentages must be between 0 and 1".into());
+        }
+        if !(0.5..=1.0).contains(&self.eat_overlap_fraction) {
+            return Err("eat_overlap_fraction must be between 0.5 and 1".into());
+        }
+        if !(self.split_recombine_delay_sec >= 0.0
+            && self.split_grav_pull_before_recombine_sec > 0.0
+            && self.eject_speed >= 0.0
+            && self.bot_view_distance >= 0.0
+            && self.view_radius_base >= 0.0
+            && self.view_radius_per_sqrt_mass >= 0.0
+            && self.view_radius_per_extra_circle >= 0.0
+            && self.visibility_margin >= 0.0)
+        {
+            return Err("Durations and speeds must not be negative".into());
+        }
+        let intervals = [
+            self.move_all_players_interval_ms,
+            self.spawn_food_interval_ms,
+            self.spawn_virus_interval_ms,
+            self.circle_decay_interval_ms,
+            self.update_leaderboard_interval_ms,
+            self.trim_kill_events_interval_ms,
+            self.trim_chat_messages_interval_ms,
+            self.update_bots_interval_ms,
+        ];
+        if intervals.contains(&0) {
+            return Err("Timer intervals must be positive".into());
+        }
+        Ok(())
+    }
+
+    fn validate_world_size(&self, world_size: u64) -> Result<(), String> {
+        let largest_spawn = self
+            .start_player_mass
+            .max(self.virus_mass)
+            .max(self.food_mass_max);
+        // The spawn code picks positions in radius..world_size - radius, which must not be empty
+        if 2.0 * mass_to_radius(largest_spawn) >= world_size as f32 {
+            return Err("world_size is too small for the spawn masses".into());
+        }
+        if world_size / self.chunk_size.max(1) as u64 > u16::MAX as u64 {
+            return Err("world_size has more chunks per side than chunk_id can hold".into());
+        }
+        if self.fixed_point_positions && world_size as f32 > MAX_FIXED_POINT_COORDINATE {
+            return Err(format!(
+                "world_size can't exceed {} with fixed_point_positions",
+                MAX_FIXED_POINT_COORDINATE
+            ));
+        }
+        Ok(())
+    }
+
+    fn intervals_differ(&self, other: &Config) -> bool {
+        self.move_all_players_interval_ms != other.move_all_players_interval_ms
+            || self.spawn_food_interval_ms != other.spawn_food_interval_ms
+            || self.spawn_virus_interval_ms != other.spawn_virus_interval_ms
+            || self.circle_decay_interval_ms != other.circle_decay_interval_ms
+            || self.update_leaderboard_interval_ms != other.update_leaderboard_interval_ms
+            || self.trim_kill_events_interval_ms != other.trim_kill_events_interval_ms
+            || self.trim_chat_messages_interval_ms != other.trim_chat_messages_interval_ms
+            || self.update_bots_interval_ms != other.update_bots_interval_ms
+    }
+}
+
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    FreeForAll,
+    /// Players are split into the arena's teams and can't eat teammates
+    Teams,
+}
+
+/// A separate world with its own entities, sharing the game rules in `Config`.
+#[spacetimedb::table(name = arena, public)]
+#[derive(Debug, Clone)]
+pub struct Arena {
+    #[primary_key]
+    #[auto_inc]
+    pub arena_id: u32,
+    pub game_mode: GameMode,
+    pub world_size: u64,
+    pub max_players: u32,
+    pub target_food_count: u32,
+    pub target_virus_count: u32,
+}
+
+impl Arena {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            arena_id: 0,
+            game_mode: GameMode::FreeForAll,
+            world_size: config.world_size,
+            max_players: config.max_players_per_arena,
+            target_food_count: config.target_food_count,
+            target_virus_count: config.target_virus_count,
+        }
+    }
+}
+
+/// Private so clients can't see the whole map, they read `visible_entities` instead.
+#[spacetimedb::table(name = entity)]
 #[derive(Debug, Clone)]
 pub struct Entity {
     #[auto_inc]
     #[primary_key]
     pub entity_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
     pub position: DbVector2,
     pub mass: u32,
+    /// Grid cell the entity is in, `cell_y << 16 | cell_x` with cells of
+    /// `Config::chunk_size`. Lets the visibility update look up just the chunks around a camera.
+    #[index(btree)]
+    pub chunk_id: u32,
+}
+
+impl Entity {
+    fn new(config: &Config, arena_id: u32, position: DbVector2, mass: u32) -> Self {
+        Self {
+            entity_id: 0,
+            arena_id,
+            position,
+            mass,
+            chunk_id: chunk_id(config.chunk_size, position),
+        }
+    }
+}
+
+pub fn chunk_id(chunk_size: u32, position: DbVector2) -> u32 {
+    let cell = |coordinate: f32| (coordinate.max(0.0) as u32 / chunk_size).min(u16::MAX as u32);
+    cell(position.y) << 16 | cell(position.x)
 }
 
-#[spacetimedb::table(name = circle, public)]
+/// Private like `entity`, clients read `visible_circles` instead.
+#[spacetimedb::table(name = circle)]
 pub struct Circle {
     #[primary_key]
     pub entity_id: u32,
     #[index(btree)]
     pub player_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
     pub direction: DbVector2,
     pub speed: f32,
     pub last_split_time: Timestamp,
 }
 
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    /// Connected but hasn't entered the game yet
+    Lobby,
+    Alive,
+    /// All circles were eaten or destroyed, waiting to respawn
+    Dead,
+    /// Watching another player, see `spectator`
+    Spectating,
+}
+
+/// Latest input sent through `update_player_input`, applied to the player's
+/// circles once per tick by `move_all_players`.
+#[spacetimedb::table(name = player_input)]
+pub struct PlayerInput {
+    #[primary_key]
+    identity: Identity,
+    #[unique]
+    player_id: u32,
+    direction: DbVector2,
+    speed: f32,
+    /// Whether the input changed since the last tick applied it
+    pending: bool,
+    rate_window_start: Timestamp,
+    calls_in_window: u32,
+}
+
+impl PlayerInput {
+    fn new(identity: Identity, player_id: u32, timestamp: Timestamp) -> Self {
+        Self {
+            identity,
+            player_id,
+            direction: DbVector2::new(0.0, 0.0),
+            speed: 0.0,
+            pending: false,
+            rate_window_start: timestamp,
+            calls_in_window: 0,
+        }
+    }
+}
+
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectateTarget {
+    /// Whoever has the most mass in the spectator's arena
+    Leader,
+    Player(u32),
+}
+
+/// What a living player sees, recomputed by `move_all_players` every tick.
+/// Private because it gives away where the player is, see `my_player_view`.
+#[spacetimedb::table(name = player_view)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerView {
+    #[primary_key]
+    pub player_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub center_of_mass: DbVector2,
+    /// Corners of the box around all of the player's circles, radii included
+    pub extent_min: DbVector2,
+    pub extent_max: DbVector2,
+    /// Grows with mass and with every extra circle, and always covers the extent
+    pub view_radius: f32,
+}
+
+#[spacetimedb::table(name = spectator, public)]
+#[derive(Debug, Clone)]
+pub struct Spectator {
+    #[primary_key]
+    identity: Identity,
+    #[index(btree)]
+    arena_id: u32,
+    target: SpectateTarget,
+    /// Player the camera is currently on, 0 while no one is alive to follow
+    followed_player_id: u32,
+    /// Center of mass of the followed player's circles, kept at the last
+    /// known spot when the followed player has none
+    camera_center: DbVector2,
+}
+
+/// An entity `identity`'s camera can see, within the view radius of the player
+/// or of whoever a spectator follows plus `Config::visibility_margin`. Kept in
+/// sync by `move_all_players`.
+#[spacetimedb::table(name = visible_entity)]
+pub struct VisibleEntity {
+    #[auto_inc]
+    #[primary_key]
+    row_id: u64,
+    #[index(btree)]
+    identity: Identity,
+    entity_id: u32,
+    #[index(btree)]
+    arena_id: u32,
+}
+
+// Clients only get the entities in their own `visible_entity` rows, so a
+// modded client can't see the rest of the map. The leaderboard, spectators,
+// player_stats, etc. stay public, which is where services like the skin
+// middleware read mass.
+
+/// The entities the caller's camera can see.
+#[spacetimedb::view(name = visible_entities, public)]
+fn visible_entities(ctx: &ViewContext) -> Vec<Entity> {
+    ctx.db
+        .visible_entity()
+        .identity()
+        .filter(ctx.sender)
+        .filter_map(|visible| ctx.db.entity().entity_id().find(visible.entity_id))
+        .collect()
+}
+
+/// The player circles among `visible_entities`.
+#[spacetimedb::view(name = visible_circles, public)]
+fn visible_circles(ctx: &ViewContext) -> Vec<Circle> {
+    ctx.db
+        .visible_entity()
+        .identity()
+        .filter(ctx.sender)
+        .filter_map(|visible| ctx.db.circle().entity_id().find(visible.entity_id))
+        .collect()
+}
+
+#[spacetimedb::view(name = my_player_view, public)]
+fn my_player_view(ctx: &ViewContext) -> Option<PlayerView> {
+    let player = ctx.db.player().identity().find(ctx.sender)?;
+    ctx.db.player_view().player_id().find(player.player_id)
+}
+
 #[spacetimedb::table(name = player, public)]
 #[spacetimedb::table(name = logged_out_player)]
 #[derive(Debug, Clone)]
@@ -63,15 +494,186 @@ pub struct Player {
     identity: Identity,
     //#[unique]
     #[auto_inc]
+    #[index(btree)]
     player_id: u32,
     name: String,
     aptos_address: String,
+    last_eject_time: Timestamp,
+    last_chat_time: Timestamp,
+    /// Set by an admin through `mute_player`; `send_chat` rejects messages until then
+    muted_until: Option<Timestamp>,
+    /// Set when the player gets kicked for flooding `update_player_input`; they can't re-enter until then
+    input_blocked_until: Option<Timestamp>,
+    state: PlayerState,
+    /// `NO_ARENA` until the player enters the game
+    #[index(btree)]
+    arena_id: u32,
+    /// `NO_TEAM` unless the player is in a `GameMode::Teams` arena
+    #[index(btree)]
+    team_id: u32,
+    /// Driven by `update_bots` instead of a client, see `spawn_bot`
+    is_bot: bool,
+}
+
+const NO_ARENA: u32 = 0;
+const NO_TEAM: u32 = 0;
+
+/// A team in a `GameMode::Teams` arena. `member_count` and `total_mass` are
+/// refreshed alongside the leaderboard.
+#[spacetimedb::table(name = team, public)]
+#[derive(Debug, Clone)]
+pub struct Team {
+    #[primary_key]
+    #[auto_inc]
+    pub team_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub name: String,
+    pub member_count: u32,
+    pub total_mass: u32,
+}
+
+#[spacetimedb::table(name = admin)]
+pub struct Admin {
+    #[primary_key]
+    identity: Identity,
+}
+
+/// Lifetime stats. Kept in their own table so they outlive the player row
+/// moving between `player` and `logged_out_player`.
+#[spacetimedb::table(name = player_stats, public)]
+#[derive(Debug, Clone)]
+pub struct PlayerStats {
+    #[primary_key]
+    pub player_id: u32,
+    pub all_time_high_mass: u32,
+    pub current_life_peak_mass: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub food_eaten: u32,
+    pub total_time_alive_ms: u64,
+    pub games_played: u32,
+    pub life_started_at: Option<Timestamp>,
+}
+
+impl PlayerStats {
+    fn new(player_id: u32) -> Self {
+        Self {
+            player_id,
+            all_time_high_mass: 0,
+            current_life_peak_mass: 0,
+            kills: 0,
+            deaths: 0,
+            food_eaten: 0,
+            total_time_alive_ms: 0,
+            games_played: 0,
+            life_started_at: None,
+        }
+    }
 }
 
 #[spacetimedb::table(name = food, public)]
 pub struct Food {
     #[primary_key]
     pub entity_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+}
+
+#[spacetimedb::table(name = virus, public)]
+pub struct Virus {
+    #[primary_key]
+    pub entity_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+}
+
+#[spacetimedb::table(name = ejected_mass, public)]
+pub struct EjectedMass {
+    #[primary_key]
+    pub entity_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub velocity: DbVector2,
+}
+
+/// Top `leaderboard_size` players of each arena, ranked from 1 within the arena.
+#[spacetimedb::table(name = leaderboard, public)]
+#[derive(PartialEq)]
+pub struct LeaderboardEntry {
+    #[auto_inc]
+    #[primary_key]
+    pub entry_id: u64,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub rank: u32,
+    pub player_id: u32,
+    pub name: String,
+    pub mass: u32,
+}
+
+/// Append-only log of circles eaten by another player, trimmed by
+/// `trim_kill_events` once rows are older than `kill_event_retention_sec`.
+#[spacetimedb::table(name = kill_event, public)]
+pub struct KillEvent {
+    #[primary_key]
+    #[auto_inc]
+    pub event_id: u64,
+    pub timestamp: Timestamp,
+    #[index(btree)]
+    pub killer_player_id: u32,
+    #[index(btree)]
+    pub victim_player_id: u32,
+    pub mass: u32,
+    pub last_circle: bool,
+}
+
+/// Trimmed by `trim_chat_messages` once rows are older than
+/// `chat_message_retention_sec`.
+#[spacetimedb::table(name = chat_message, public)]
+pub struct ChatMessage {
+    #[primary_key]
+    #[auto_inc]
+    pub message_id: u64,
+    pub sender_player_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub text: String,
+    pub timestamp: Timestamp,
+}
+
+#[spacetimedb::table(name = round, public)]
+#[derive(Debug, Clone)]
+pub struct Round {
+    #[primary_key]
+    #[auto_inc]
+    pub round_id: u32,
+    #[index(btree)]
+    pub arena_id: u32,
+    pub started_at: Timestamp,
+    pub ends_at: Timestamp,
+    pub ended: bool,
+}
+
+#[spacetimedb::table(name = round_result, public)]
+pub struct RoundResult {
+    #[primary_key]
+    #[auto_inc]
+    pub result_id: u64,
+    #[index(btree)]
+    pub round_id: u32,
+    pub rank: u32,
+    pub player_id: u32,
+    pub name: String,
+    pub mass: u32,
+}
+
+/// When `move_all_players` last ran, used to work out how much time it has to simulate.
+#[spacetimedb::table(name = tick_state)]
+pub struct TickState {
+    #[primary_key]
+    id: u32,
+    last_tick_at: Timestamp,
 }
 
 #[spacetimedb::table(name = move_all_players_timer, scheduled(move_all_players))]
@@ -90,6 +692,14 @@ pub struct SpawnFoodTimer {
     scheduled_at: spacetimedb::ScheduleAt,
 }
 
+#[spacetimedb::table(name = spawn_virus_timer, scheduled(spawn_virus))]
+pub struct SpawnVirusTimer {
+    #[primary_key]
+    #[auto_inc]
+    scheduled_id: u64,
+    scheduled_at: spacetimedb::ScheduleAt,
+}
+
 #[spacetimedb::table(name = circle_decay_timer, scheduled(circle_decay))]
 pub struct CircleDecayTimer {
     #[primary_key]
@@ -107,55 +717,475 @@ pub struct CircleRecombineTimer {
     player_id: u32,
 }
 
-#[spacetimedb::table(name = consume_entity_timer, scheduled(consume_entity))]
-pub struct ConsumeEntityTimer {
+#[spacetimedb::table(name = update_leaderboard_timer, scheduled(update_leaderboard))]
+pub struct UpdateLeaderboardTimer {
+    #[primary_key]
+    #[auto_inc]
+    scheduled_id: u64,
+    scheduled_at: spacetimedb::ScheduleAt,
+}
+
+#[spacetimedb::table(name = trim_kill_events_timer, scheduled(trim_kill_events))]
+pub struct TrimKillEventsTimer {
+    #[primary_key]
+    #[auto_inc]
+    scheduled_id: u64,
+    scheduled_at: spacetimedb::ScheduleAt,
+}
+
+#[spacetimedb::table(name = trim_chat_messages_timer, scheduled(trim_chat_messages))]
+pub struct TrimChatMessagesTimer {
     #[primary_key]
     #[auto_inc]
     scheduled_id: u64,
     scheduled_at: spacetimedb::ScheduleAt,
-    consumed_entity_id: u32,
-    consumer_entity_id: u32,
+}
+
+#[spacetimedb::table(name = update_bots_timer, scheduled(update_bots))]
+pub struct UpdateBotsTimer {
+    #[primary_key]
+    #[auto_inc]
+    scheduled_id: u64,
+    scheduled_at: spacetimedb::ScheduleAt,
+}
+
+#[spacetimedb::table(name = end_round_timer, scheduled(end_round))]
+pub struct EndRoundTimer {
+    #[primary_key]
+    #[auto_inc]
+    scheduled_id: u64,
+    scheduled_at: spacetimedb::ScheduleAt,
+    round_id: u32,
 }
 
 #[spacetimedb::reducer(init)]
 pub fn init(ctx: &ReducerContext) -> Result<(), String> {
     log::info!("Initializing...");
-    ctx.db.config().try_insert(Config {
-        id: 0,
-        world_size: 1000,
+    let config = ctx.db.config().try_insert(Config::default())?;
+    insert_arena(ctx, &config, Arena::from_config(&config))?;
+    ctx.db.admin().try_insert(Admin {
+        identity: ctx.sender,
     })?;
+    schedule_game_timers(ctx, &config)?;
+    Ok(())
+}
+
+/// Rejects calls that come from neither the module itself, as scheduled
+/// reducers do, nor an admin. Clients can otherwise invoke scheduled
+/// reducers directly with a forged timer row.
+fn ensure_internal_or_admin(ctx: &ReducerContext) -> Result<(), String> {
+    if ctx.sender == ctx.identity() || ctx.db.admin().identity().find(ctx.sender).is_some() {
+        Ok(())
+    } else {
+        Err("Only the module or an admin can call this reducer".into())
+    }
+}
+
+#[spacetimedb::reducer]
+pub fn add_admin(ctx: &ReducerContext, identity: Identity) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    if ctx.db.admin().identity().find(identity).is_none() {
+        ctx.db.admin().insert(Admin { identity });
+        log::info!("Added admin {}", identity);
+    }
+    Ok(())
+}
+
+#[spacetimedb::reducer]
+pub fn remove_admin(ctx: &ReducerContext, identity: Identity) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    if ctx.db.admin().count() <= 1 {
+        return Err("Can't remove the last admin".into());
+    }
+    if ctx.db.admin().identity().delete(identity) {
+        log::info!("Removed admin {}", identity);
+    }
+    Ok(())
+}
+
+fn get_config(ctx: &ReducerContext) -> Result<Config, String> {
+    Ok(ctx.db.config().id().find(0).ok_or("Config not found")?)
+}
+
+fn get_arena(ctx: &ReducerContext, arena_id: u32) -> Result<Arena, String> {
+    Ok(ctx
+        .db
+        .arena()
+        .arena_id()
+        .find(arena_id)
+        .ok_or("Arena not found")?)
+}
+
+/// Humans in the arena, bots make room for them so they don't take up slots
+fn arena_player_count(ctx: &ReducerContext, arena_id: u32) -> u32 {
+    ctx.db
+        .player()
+        .arena_id()
+        .filter(arena_id)
+        .filter(|p| !p.is_bot)
+        .count() as u32
+}
+
+fn arena_has_active_players(ctx: &ReducerContext, arena_id: u32) -> bool {
+    ctx.db
+        .player()
+        .arena_id()
+        .filter(arena_id)
+        .any(|p| p.state != PlayerState::Spectating)
+}
+
+#[spacetimedb::reducer]
+pub fn create_arena(
+    ctx: &ReducerContext,
+    world_size: u64,
+    max_players: u32,
+    target_food_count: u32,
+    target_virus_count: u32,
+    game_mode: GameMode,
+    team_count: u32,
+) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    config.validate_world_size(world_size)?;
+    if max_players == 0 {
+        return Err("max_players must be positive".into());
+    }
+    if game_mode == GameMode::Teams && team_count < 2 {
+        return Err("Team arenas need at least two teams".into());
+    }
+    let arena = insert_arena(
+        ctx,
+        &config,
+        Arena {
+            arena_id: 0,
+            game_mode,
+            world_size,
+            max_players,
+            target_food_count,
+            target_virus_count,
+        },
+    )?;
+    if game_mode == GameMode::Teams {
+        for i in 1..=team_count {
+            ctx.db.team().try_insert(Team {
+                team_id: 0,
+                arena_id: arena.arena_id,
+                name: format!("Team {}", i),
+                member_count: 0,
+                total_mass: 0,
+            })?;
+        }
+    }
+    log::info!("Created arena {}", arena.arena_id);
+    Ok(())
+}
+
+fn insert_arena(ctx: &ReducerContext, config: &Config, arena: Arena) -> Result<Arena, String> {
+    let arena = ctx.db.arena().try_insert(arena)?;
+    start_round(ctx, config, arena.arena_id)?;
+    Ok(arena)
+}
+
+fn start_round(ctx: &ReducerContext, config: &Config, arena_id: u32) -> Result<(), String> {
+    if config.round_duration_sec == 0 {
+        return Ok(());
+    }
+    let ends_at =
+        ctx.timestamp + TimeDuration::from(Duration::from_secs(config.round_duration_sec));
+    let round = ctx.db.round().try_insert(Round {
+        round_id: 0,
+        arena_id,
+        started_at: ctx.timestamp,
+        ends_at,
+        ended: false,
+    })?;
+    ctx.db.end_round_timer().try_insert(EndRoundTimer {
+        scheduled_id: 0,
+        scheduled_at: ScheduleAt::Time(ends_at),
+        round_id: round.round_id,
+    })?;
+    log::info!("Started round {} in arena {}", round.round_id, arena_id);
+    Ok(())
+}
+
+/// Records the top finishers of the round, wipes the arena, respawns everyone
+/// who was playing in it and starts the next round.
+#[spacetimedb::reducer]
+pub fn end_round(ctx: &ReducerContext, timer: EndRoundTimer) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    let mut round = ctx
+        .db
+        .round()
+        .round_id()
+        .find(timer.round_id)
+        .ok_or("Round not found")?;
+    if round.ended {
+        return Ok(());
+    }
+    let arena = get_arena(ctx, round.arena_id)?;
+
+    let player_masses =
+        total_mass_by_player(ctx, ctx.db.circle().arena_id().filter(arena.arena_id));
+    let mut finishers: Vec<Player> = ctx
+        .db
+        .player()
+        .arena_id()
+        .filter(arena.arena_id)
+        .filter(|p| player_masses.contains_key(&p.player_id))
+        .collect();
+    finishers.sort_by_key(|p| (std::cmp::Reverse(player_masses[&p.player_id]), p.player_id));
+    for (i, player) in finishers
+        .into_iter()
+        .take(config.round_result_count as usize)
+        .enumerate()
+    {
+        ctx.db.round_result().insert(RoundResult {
+            result_id: 0,
+            round_id: round.round_id,
+            rank: i as u32 + 1,
+            player_id: player.player_id,
+            mass: player_masses[&player.player_id],
+            name: player.name,
+        });
+    }
+
+    clear_arena(ctx, arena.arena_id);
+    for mut player in ctx.db.player().arena_id().filter(arena.arena_id) {
+        match player.state {
+            PlayerState::Alive => end_player_life(ctx, player.player_id, false),
+            PlayerState::Dead => {}
+            PlayerState::Lobby | PlayerState::Spectating => continue,
+        }
+        spawn_player_initial_circle(ctx, player.player_id, &arena)?;
+        player.state = PlayerState::Alive;
+        ctx.db.player().identity().update(player);
+    }
+
+    round.ended = true;
+    log::info!("Round {} in arena {} ended", round.round_id, arena.arena_id);
+    ctx.db.round().round_id().update(round);
+    start_round(ctx, &config, arena.arena_id)?;
+
+    Ok(())
+}
+
+/// Removes every entity from the arena without counting anyone as dead.
+fn clear_arena(ctx: &ReducerContext, arena_id: u32) {
+    for entity in ctx.db.entity().arena_id().filter(arena_id) {
+        ctx.db.food().entity_id().delete(entity.entity_id);
+        ctx.db.virus().entity_id().delete(entity.entity_id);
+        ctx.db.ejected_mass().entity_id().delete(entity.entity_id);
+        ctx.db.circle().entity_id().delete(entity.entity_id);
+        ctx.db.entity().entity_id().delete(entity.entity_id);
+    }
+    for visible in ctx.db.visible_entity().arena_id().filter(arena_id) {
+        ctx.db.visible_entity().row_id().delete(visible.row_id);
+    }
+}
+
+/// Puts the player on the requested team, or on the team with the fewest
+/// players. Always `NO_TEAM` outside of team arenas.
+fn assign_team(ctx: &ReducerContext, arena: &Arena, requested: Option<u32>) -> Result<u32, String> {
+    if arena.game_mode != GameMode::Teams {
+        if requested.is_some() {
+            return Err("Arena has no teams".into());
+        }
+        return Ok(NO_TEAM);
+    }
+    if let Some(team_id) = requested {
+        let team = ctx
+            .db
+            .team()
+            .team_id()
+            .find(team_id)
+            .ok_or("Team not found")?;
+        if team.arena_id != arena.arena_id {
+            return Err("Team is not in this arena".into());
+        }
+        return Ok(team_id);
+    }
+
+    let smallest_team = ctx
+        .db
+        .team()
+        .arena_id()
+        .filter(arena.arena_id)
+        .min_by_key(|team| {
+            (
+                ctx.db.player().team_id().filter(team.team_id).count(),
+                team.team_id,
+            )
+        })
+        .ok_or("Arena has no teams")?;
+    Ok(smallest_team.team_id)
+}
+
+/// Picks the requested arena if it has room, otherwise the fullest arena
+/// that still has room, creating a new one when every arena is full.
+fn assign_arena(ctx: &ReducerContext, requested: Option<u32>) -> Result<Arena, String> {
+    if let Some(arena_id) = requested {
+        let arena = get_arena(ctx, arena_id)?;
+        if arena_player_count(ctx, arena_id) >= arena.max_players {
+            return Err("Arena is full".into());
+        }
+        return Ok(arena);
+    }
+
+    let fullest_open_arena = ctx
+        .db
+        .arena()
+        .iter()
+        .map(|arena| (arena_player_count(ctx, arena.arena_id), arena))
+        .filter(|(player_count, arena)| *player_count < arena.max_players)
+        .max_by_key(|(player_count, arena)| (*player_count, std::cmp::Reverse(arena.arena_id)));
+    match fullest_open_arena {
+        Some((_, arena)) => Ok(arena),
+        None => {
+            let config = get_config(ctx)?;
+            let arena = insert_arena(ctx, &config, Arena::from_config(&config))?;
+            log::info!("All arenas full, created arena {}", arena.arena_id);
+            Ok(arena)
+        }
+    }
+}
+
+fn interval_ms(ms: u64) -> ScheduleAt {
+    ScheduleAt::Interval(Duration::from_millis(ms).into())
+}
+
+/// (Re)creates the repeating game timers with the intervals from `config`.
+fn schedule_game_timers(ctx: &ReducerContext, config: &Config) -> Result<(), String> {
+    for timer in ctx.db.circle_decay_timer().iter() {
+        ctx.db.circle_decay_timer().delete(timer);
+    }
+    for timer in ctx.db.spawn_food_timer().iter() {
+        ctx.db.spawn_food_timer().delete(timer);
+    }
+    for timer in ctx.db.spawn_virus_timer().iter() {
+        ctx.db.spawn_virus_timer().delete(timer);
+    }
+    for timer in ctx.db.update_leaderboard_timer().iter() {
+        ctx.db.update_leaderboard_timer().delete(timer);
+    }
+    for timer in ctx.db.move_all_players_timer().iter() {
+        ctx.db.move_all_players_timer().delete(timer);
+    }
+    for timer in ctx.db.trim_kill_events_timer().iter() {
+        ctx.db.trim_kill_events_timer().delete(timer);
+    }
+    for timer in ctx.db.trim_chat_messages_timer().iter() {
+        ctx.db.trim_chat_messages_timer().delete(timer);
+    }
+    for timer in ctx.db.update_bots_timer().iter() {
+        ctx.db.update_bots_timer().delete(timer);
+    }
+
     ctx.db.circle_decay_timer().try_insert(CircleDecayTimer {
         scheduled_id: 0,
-        scheduled_at: ScheduleAt::Interval(Duration::from_secs(5).into()),
+        scheduled_at: interval_ms(config.circle_decay_interval_ms),
     })?;
     ctx.db.spawn_food_timer().try_insert(SpawnFoodTimer {
         scheduled_id: 0,
-        scheduled_at: ScheduleAt::Interval(Duration::from_millis(500).into()),
+        scheduled_at: interval_ms(config.spawn_food_interval_ms),
+    })?;
+    ctx.db.spawn_virus_timer().try_insert(SpawnVirusTimer {
+        scheduled_id: 0,
+        scheduled_at: interval_ms(config.spawn_virus_interval_ms),
     })?;
+    ctx.db
+        .update_leaderboard_timer()
+        .try_insert(UpdateLeaderboardTimer {
+            scheduled_id: 0,
+            scheduled_at: interval_ms(config.update_leaderboard_interval_ms),
+        })?;
     ctx.db
         .move_all_players_timer()
         .try_insert(MoveAllPlayersTimer {
             scheduled_id: 0,
-            scheduled_at: ScheduleAt::Interval(Duration::from_millis(50).into()),
+            scheduled_at: interval_ms(config.move_all_players_interval_ms),
+        })?;
+    ctx.db
+        .trim_kill_events_timer()
+        .try_insert(TrimKillEventsTimer {
+            scheduled_id: 0,
+            scheduled_at: interval_ms(config.trim_kill_events_interval_ms),
+        })?;
+    ctx.db
+        .trim_chat_messages_timer()
+        .try_insert(TrimChatMessagesTimer {
+            scheduled_id: 0,
+            scheduled_at: interval_ms(config.trim_chat_messages_interval_ms),
         })?;
+    ctx.db.update_bots_timer().try_insert(UpdateBotsTimer {
+        scheduled_id: 0,
+        scheduled_at: interval_ms(config.update_bots_interval_ms),
+    })?;
+    Ok(())
+}
+
+#[spacetimedb::reducer]
+pub fn update_config(ctx: &ReducerContext, mut config: Config) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    config.id = 0;
+    config.validate()?;
+
+    let old_config = get_config(ctx)?;
+    if config == old_config {
+        return Ok(());
+    }
+    // The new masses, chunk size or fixed-point limit have to fit the existing arenas too
+    for arena in ctx.db.arena().iter() {
+        config
+            .validate_world_size(arena.world_size)
+            .map_err(|err| format!("Arena {}: {}", arena.arena_id, err))?;
+    }
+    if config.intervals_differ(&old_config) {
+        schedule_game_timers(ctx, &config)?;
+    }
+    if config.chunk_size != old_config.chunk_size {
+        for mut entity in ctx.db.entity().iter() {
+            entity.chunk_id = chunk_id(config.chunk_size, entity.position);
+            ctx.db.entity().entity_id().update(entity);
+        }
+    }
+    if old_config.round_duration_sec == 0 && config.round_duration_sec > 0 {
+        for arena in ctx.db.arena().iter() {
+            start_round(ctx, &config, arena.arena_id)?;
+        }
+    }
+    log::info!("Game rules updated: {:?}", config);
+    ctx.db.config().id().update(config);
+
     Ok(())
 }
 
 #[spacetimedb::reducer(client_connected)]
 pub fn connect(ctx: &ReducerContext) -> Result<(), String> {
-    if let Some(player) = ctx.db.logged_out_player().identity().find(&ctx.sender) {
+    if let Some(player) = ctx.db.logged_out_player().identity().find(ctx.sender) {
         ctx.db.player().insert(player.clone());
         ctx.db
             .logged_out_player()
             .identity()
-            .delete(&player.identity);
+            .delete(player.identity);
     } else {
-        ctx.db.player().try_insert(Player {
+        let player = ctx.db.player().try_insert(Player {
             identity: ctx.sender,
             player_id: 0,
             name: String::new(),
             aptos_address: String::new(),
+            last_eject_time: Timestamp::UNIX_EPOCH,
+            last_chat_time: Timestamp::UNIX_EPOCH,
+            muted_until: None,
+            input_blocked_until: None,
+            state: PlayerState::Lobby,
+            arena_id: NO_ARENA,
+            team_id: NO_TEAM,
+            is_bot: false,
         })?;
+        ctx.db
+            .player_stats()
+            .try_insert(PlayerStats::new(player.player_id))?;
     }
     Ok(())
 }
@@ -166,71 +1196,185 @@ pub fn disconnect(ctx: &ReducerContext) -> Result<(), String> {
         .db
         .player()
         .identity()
-        .find(&ctx.sender)
+        .find(ctx.sender)
         .ok_or("Player not found")?;
-    let player_id = player.player_id;
+    log_out_player(ctx, player);
+
+    Ok(())
+}
+
+/// Moves the player to `logged_out_player` and removes everything they had in their arena.
+fn log_out_player(ctx: &ReducerContext, player: Player) {
+    let identity = player.identity;
+    // Their circles are removed, so they start over from the lobby when they return
+    let player = remove_from_arena(ctx, player);
     ctx.db.logged_out_player().insert(player);
-    ctx.db.player().identity().delete(&ctx.sender);
+    ctx.db.player().identity().delete(identity);
+}
+
+/// Removes everything the player had in their arena and returns them as a
+/// lobby player, ready to `enter_game` again. The caller stores the row.
+fn remove_from_arena(ctx: &ReducerContext, player: Player) -> Player {
+    let identity = player.identity;
+    let player_id = player.player_id;
+    if player.state == PlayerState::Alive {
+        end_player_life(ctx, player_id, false);
+    }
+    ctx.db.spectator().identity().delete(identity);
+    ctx.db.player_input().identity().delete(identity);
+    ctx.db.player_view().player_id().delete(player_id);
+    ctx.db.visible_entity().identity().delete(identity);
 
     // Remove any circles from the arena
-    for circle in ctx.db.circle().player_id().filter(&player_id) {
-        ctx.db.entity().entity_id().delete(&circle.entity_id);
-        ctx.db.circle().entity_id().delete(&circle.entity_id);
+    for circle in ctx.db.circle().player_id().filter(player_id) {
+        ctx.db.entity().entity_id().delete(circle.entity_id);
+        ctx.db.circle().entity_id().delete(circle.entity_id);
     }
 
-    Ok(())
+    Player {
+        state: PlayerState::Lobby,
+        arena_id: NO_ARENA,
+        team_id: NO_TEAM,
+        ..player
+    }
 }
 
+/// Enters the fullest arena that still has room, see `enter_arena` to pick one.
 #[spacetimedb::reducer]
 pub fn enter_game(ctx: &ReducerContext, name: String, aptos_address: String) -> Result<(), String> {
+    enter_player(ctx, name, aptos_address, None, None)
+}
+
+/// Like `enter_game`, but joins the requested arena and, in team arenas, team.
+#[spacetimedb::reducer]
+pub fn enter_arena(
+    ctx: &ReducerContext,
+    name: String,
+    aptos_address: String,
+    arena_id: Option<u32>,
+    team_id: Option<u32>,
+) -> Result<(), String> {
+    enter_player(ctx, name, aptos_address, arena_id, team_id)
+}
+
+fn enter_player(
+    ctx: &ReducerContext,
+    name: String,
+    aptos_address: String,
+    arena_id: Option<u32>,
+    team_id: Option<u32>,
+) -> Result<(), String> {
     log::info!("Creating player with name {}", name);
-    let mut player: Player = ctx.db.player().identity().find(ctx.sender).ok_or("")?;
+    let mut player: Player = ctx
+        .db
+        .player()
+        .identity()
+        .find(ctx.sender)
+        .ok_or("Player not found, reconnect to enter the game")?;
+    if player.state != PlayerState::Lobby {
+        return Err("Player has already entered the game".into());
+    }
+    if player
+        .input_blocked_until
+        .is_some_and(|until| ctx.timestamp < until)
+    {
+        return Err("Player was kicked for sending too many inputs, try again later".into());
+    }
+    let arena = assign_arena(ctx, arena_id)?;
+    let team_id = assign_team(ctx, &arena, team_id)?;
     let player_id = player.player_id;
     player.name = name;
     player.aptos_address = aptos_address;
+    player.state = PlayerState::Alive;
+    player.arena_id = arena.arena_id;
+    player.team_id = team_id;
     ctx.db.player().identity().update(player);
-    spawn_player_initial_circle(ctx, player_id)?;
+    spawn_player_initial_circle(ctx, player_id, &arena)?;
 
     Ok(())
 }
 
-fn spawn_player_initial_circle(ctx: &ReducerContext, player_id: u32) -> Result<Entity, String> {
+fn spawn_player_initial_circle(
+    ctx: &ReducerContext,
+    player_id: u32,
+    arena: &Arena,
+) -> Result<Entity, String> {
     let mut rng = ctx.rng();
-    let world_size = ctx
-        .db
-        .config()
-        .id()
-        .find(&0)
-        .ok_or("Config not found")?
-        .world_size;
-    let player_start_radius = mass_to_radius(START_PLAYER_MASS);
+    let config = get_config(ctx)?;
+    let world_size = arena.world_size;
+    let player_start_radius = mass_to_radius(config.start_player_mass);
     let x = rng.gen_range(player_start_radius..(world_size as f32 - player_start_radius));
     let y = rng.gen_range(player_start_radius..(world_size as f32 - player_start_radius));
-    spawn_circle_at(
+    let entity = spawn_circle_at(
         ctx,
         player_id,
-        START_PLAYER_MASS,
+        arena.arena_id,
+        config.start_player_mass,
         DbVector2 { x, y },
         ctx.timestamp,
-    )
+    )?;
+
+    update_player_stats(ctx, player_id, |stats| {
+        stats.games_played += 1;
+        stats.current_life_peak_mass = entity.mass;
+        stats.all_time_high_mass = stats.all_time_high_mass.max(entity.mass);
+        stats.life_started_at = Some(ctx.timestamp);
+    });
+
+    Ok(entity)
+}
+
+fn update_player_stats(
+    ctx: &ReducerContext,
+    player_id: u32,
+    update: impl FnOnce(&mut PlayerStats),
+) {
+    match ctx.db.player_stats().player_id().find(player_id) {
+        Some(mut stats) => {
+            update(&mut stats);
+            ctx.db.player_stats().player_id().update(stats);
+        }
+        None => {
+            let mut stats = PlayerStats::new(player_id);
+            update(&mut stats);
+            ctx.db.player_stats().insert(stats);
+        }
+    }
+}
+
+fn end_player_life(ctx: &ReducerContext, player_id: u32, died: bool) {
+    update_player_stats(ctx, player_id, |stats| {
+        if let Some(life_started_at) = stats.life_started_at.take() {
+            let time_alive = ctx
+                .timestamp
+                .duration_since(life_started_at)
+                .unwrap_or(Duration::ZERO);
+            stats.total_time_alive_ms += time_alive.as_millis() as u64;
+        }
+        if died {
+            stats.deaths += 1;
+        }
+    });
 }
 
 fn spawn_circle_at(
     ctx: &ReducerContext,
     player_id: u32,
+    arena_id: u32,
     mass: u32,
     position: DbVector2,
     timestamp: Timestamp,
 ) -> Result<Entity, String> {
-    let entity = ctx.db.entity().try_insert(Entity {
-        entity_id: 0,
-        position,
-        mass,
-    })?;
+    let config = get_config(ctx)?;
+    let entity = ctx
+        .db
+        .entity()
+        .try_insert(Entity::new(&config, arena_id, position, mass))?;
 
     ctx.db.circle().try_insert(Circle {
         entity_id: entity.entity_id,
         player_id,
+        arena_id,
         direction: DbVector2 { x: 0.0, y: 1.0 },
         speed: 0.0,
         last_split_time: timestamp,
@@ -240,28 +1384,109 @@ fn spawn_circle_at(
 
 #[spacetimedb::reducer]
 pub fn respawn(ctx: &ReducerContext) -> Result<(), String> {
-    let player = ctx
+    let mut player = ctx
         .db
         .player()
         .identity()
-        .find(&ctx.sender)
+        .find(ctx.sender)
         .ok_or("No such player found")?;
+    if !matches!(player.state, PlayerState::Dead | PlayerState::Spectating) {
+        return Err("Only dead or spectating players can respawn".into());
+    }
 
-    spawn_player_initial_circle(ctx, player.player_id)?;
+    let arena = get_arena(ctx, player.arena_id)?;
+    spawn_player_initial_circle(ctx, player.player_id, &arena)?;
+    player.state = PlayerState::Alive;
+    ctx.db.player().identity().update(player);
+    ctx.db.spectator().identity().delete(ctx.sender);
 
     Ok(())
 }
 
+#[spacetimedb::reducer]
+pub fn spectate(ctx: &ReducerContext, target: SpectateTarget) -> Result<(), String> {
+    let mut player = ctx
+        .db
+        .player()
+        .identity()
+        .find(ctx.sender)
+        .ok_or("No such player found")?;
+    if !matches!(player.state, PlayerState::Dead | PlayerState::Spectating) {
+        return Err("Only dead or spectating players can spectate".into());
+    }
+    if let SpectateTarget::Player(player_id) = target {
+        ctx.db
+            .player()
+            .player_id()
+            .filter(player_id)
+            .find(|p| p.arena_id == player.arena_id)
+            .ok_or("Player to spectate is not in this arena")?;
+    }
+
+    let mut spectator = Spectator {
+        identity: ctx.sender,
+        arena_id: player.arena_id,
+        target,
+        followed_player_id: 0,
+        camera_center: DbVector2::new(0.0, 0.0),
+    };
+    if let Some(existing) = ctx.db.spectator().identity().find(ctx.sender) {
+        spectator.camera_center = existing.camera_center;
+        ctx.db.spectator().identity().delete(ctx.sender);
+    }
+    ctx.db
+        .spectator()
+        .try_insert(follow_target(ctx, spectator))?;
+    player.state = PlayerState::Spectating;
+    ctx.db.player().identity().update(player);
+
+    Ok(())
+}
+
+/// Resolves the spectator's target and moves the camera onto it.
+fn follow_target(ctx: &ReducerContext, mut spectator: Spectator) -> Spectator {
+    spectator.followed_player_id = match spectator.target {
+        SpectateTarget::Player(player_id) => player_id,
+        SpectateTarget::Leader => {
+            total_mass_by_player(ctx, ctx.db.circle().arena_id().filter(spectator.arena_id))
+                .into_iter()
+                .max_by_key(|&(player_id, mass)| (mass, std::cmp::Reverse(player_id)))
+                .map_or(0, |(player_id, _)| player_id)
+        }
+    };
+    let entities: Vec<Entity> = ctx
+        .db
+        .circle()
+        .player_id()
+        .filter(spectator.followed_player_id)
+        .filter_map(|c| ctx.db.entity().entity_id().find(c.entity_id))
+        .collect();
+    if !entities.is_empty() {
+        spectator.camera_center = calculate_center_of_mass(&entities);
+    }
+    spectator
+}
+
+fn update_arena_spectators(ctx: &ReducerContext, arena: &Arena) {
+    for spectator in ctx.db.spectator().arena_id().filter(arena.arena_id) {
+        let spectator = follow_target(ctx, spectator);
+        ctx.db.spectator().identity().update(spectator);
+    }
+}
+
 #[spacetimedb::reducer]
 pub fn suicide(ctx: &ReducerContext) -> Result<(), String> {
     let player = ctx
         .db
         .player()
         .identity()
-        .find(&ctx.sender)
+        .find(ctx.sender)
         .ok_or("No such player found")?;
+    if player.state != PlayerState::Alive {
+        return Err("Player is not alive".into());
+    }
 
-    for circle in ctx.db.circle().player_id().filter(&player.player_id) {
+    for circle in ctx.db.circle().player_id().filter(player.player_id) {
         destroy_entity(ctx, circle.entity_id)?;
     }
 
@@ -274,214 +1499,508 @@ pub fn update_player_input(ctx: &ReducerContext, direction: DbVector2) -> Result
         .db
         .player()
         .identity()
-        .find(&ctx.sender)
+        .find(ctx.sender)
         .ok_or("Player not found")?;
-    for mut circle in ctx.db.circle().player_id().filter(&player.player_id) {
-        circle.direction = direction.normalized();
-        circle.speed = direction.magnitude().clamp(0.0, 1.0);
-        ctx.db.circle().entity_id().update(circle);
+    if player
+        .input_blocked_until
+        .is_some_and(|until| ctx.timestamp < until)
+    {
+        return Err("Player is blocked from sending inputs".into());
+    }
+
+    let mut input = ctx
+        .db
+        .player_input()
+        .identity()
+        .find(ctx.sender)
+        .unwrap_or(PlayerInput::new(
+            ctx.sender,
+            player.player_id,
+            ctx.timestamp,
+        ));
+    let window_age = ctx
+        .timestamp
+        .duration_since(input.rate_window_start)
+        .unwrap_or(Duration::ZERO);
+    if window_age >= Duration::from_secs(1) {
+        input.rate_window_start = ctx.timestamp;
+        input.calls_in_window = 0;
+    }
+    input.calls_in_window += 1;
+    let config = get_config(ctx)?;
+    if input.calls_in_window > config.max_player_inputs_per_sec {
+        // There's no way to close the client's connection from here, so send them back to the lobby instead.
+        // `remove_from_arena` drops the call counter, the block keeps them from coming straight back
+        log::warn!(
+            "Kicking player {} for sending too many inputs",
+            player.player_id
+        );
+        let mut player = remove_from_arena(ctx, player);
+        player.input_blocked_until = Some(
+            ctx.timestamp + TimeDuration::from(Duration::from_secs(config.input_abuse_block_sec)),
+        );
+        ctx.db.player().identity().update(player);
+        return Ok(());
+    }
+    if !(direction.x.is_finite() && direction.y.is_finite()) {
+        // An error would roll back the call count above, so the input is only dropped
+        log::warn!("Dropping non-finite input from player {}", player.player_id);
+        ctx.db.player_input().identity().delete(ctx.sender);
+        ctx.db.player_input().insert(input);
+        return Ok(());
     }
+
+    input.direction = direction.normalized();
+    input.speed = direction.magnitude().clamp(0.0, 1.0);
+    input.pending = true;
+    ctx.db.player_input().identity().delete(ctx.sender);
+    ctx.db.player_input().insert(input);
     Ok(())
 }
 
-fn is_overlapping(a: &Entity, b: &Entity) -> bool {
-    let dx = a.position.x - b.position.x;
-    let dy = a.position.y - b.position.y;
-    let distance_sq = dx * dx + dy * dy;
-
-    let radius_a = mass_to_radius(a.mass);
-    let radius_b = mass_to_radius(b.mass);
+#[spacetimedb::reducer]
+pub fn send_chat(ctx: &ReducerContext, text: String) -> Result<(), String> {
+    let mut player = ctx
+        .db
+        .player()
+        .identity()
+        .find(ctx.sender)
+        .ok_or("Player not found")?;
+    if player.arena_id == NO_ARENA {
+        return Err("Player is not in an arena".into());
+    }
+    if player
+        .muted_until
+        .is_some_and(|until| ctx.timestamp < until)
+    {
+        return Err("Player is muted".into());
+    }
+    let config = get_config(ctx)?;
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Message is empty".into());
+    }
+    if text.chars().count() > config.chat_max_length as usize {
+        return Err(format!(
+            "Message is longer than {} characters",
+            config.chat_max_length
+        ));
+    }
+    let since_last_chat = ctx
+        .timestamp
+        .duration_since(player.last_chat_time)
+        .unwrap_or(Duration::ZERO);
+    if since_last_chat < Duration::from_millis(config.chat_cooldown_ms) {
+        return Err("Sending messages too quickly".into());
+    }
 
-    // If the distance between the two circle centers is less than the
-    // maximum radius, then the center of the smaller circle is inside
-    // the larger circle. This gives some leeway for the circles to overlap
-    // before being eaten.
-    let max_radius = f32::max(radius_a, radius_b);
-    distance_sq <= max_radius * max_radius
-}
+    ctx.db.chat_message().insert(ChatMessage {
+        message_id: 0,
+        sender_player_id: player.player_id,
+        arena_id: player.arena_id,
+        text: text.to_string(),
+        timestamp: ctx.timestamp,
+    });
+    player.last_chat_time = ctx.timestamp;
+    ctx.db.player().identity().update(player);
 
-fn mass_to_radius(mass: u32) -> f32 {
-    (mass as f32).sqrt()
+    Ok(())
 }
 
-fn mass_to_max_move_speed(mass: u32) -> f32 {
-    2.0 * START_PLAYER_SPEED as f32 / (1.0 + (mass as f32 / START_PLAYER_MASS as f32).sqrt())
+/// Mutes a player for `duration_sec` seconds; 0 lifts an existing mute.
+#[spacetimedb::reducer]
+pub fn mute_player(ctx: &ReducerContext, player_id: u32, duration_sec: u64) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let mut player = ctx
+        .db
+        .player()
+        .player_id()
+        .filter(player_id)
+        .next()
+        .ok_or("Player not found")?;
+    player.muted_until = if duration_sec == 0 {
+        None
+    } else {
+        Some(ctx.timestamp + TimeDuration::from(Duration::from_secs(duration_sec)))
+    };
+    log::info!("Player {} muted until {:?}", player_id, player.muted_until);
+    ctx.db.player().identity().update(player);
+    Ok(())
 }
 
 #[spacetimedb::reducer]
 pub fn move_all_players(ctx: &ReducerContext, _timer: MoveAllPlayersTimer) -> Result<(), String> {
-    // TODO identity check
+    ensure_internal_or_admin(ctx)?;
     // let span = spacetimedb::log_stopwatch::LogStopwatch::new("tick");
-    let world_size = ctx
-        .db
-        .config()
-        .id()
-        .find(0)
-        .ok_or("Config not found")?
-        .world_size;
+    let config = get_config(ctx)?;
+    let sim_config = config.sim_config();
+    let dt = tick_dt(ctx, &config);
+    let inputs = take_player_inputs(ctx);
+    for arena in ctx.db.arena().iter() {
+        move_arena_players(ctx, &config, &sim_config, dt, &arena, &inputs);
+        update_arena_spectators(ctx, &arena);
+        update_arena_visibility(ctx, &config, &arena);
+    }
+
+    // span.end();
+    Ok(())
+}
 
-    let mut circle_directions: HashMap<u32, DbVector2> = ctx
+/// Seconds since the previous tick, capped at `max_tick_dt_ms`.
+fn tick_dt(ctx: &ReducerContext, config: &Config) -> f32 {
+    let elapsed = match ctx.db.tick_state().id().find(0) {
+        Some(mut state) => {
+            let elapsed = ctx
+                .timestamp
+                .duration_since(state.last_tick_at)
+                .unwrap_or(Duration::ZERO);
+            state.last_tick_at = ctx.timestamp;
+            ctx.db.tick_state().id().update(state);
+            elapsed
+        }
+        //First tick, assume it ran on schedule
+        None => {
+            ctx.db.tick_state().insert(TickState {
+                id: 0,
+                last_tick_at: ctx.timestamp,
+            });
+            Duration::from_millis(config.move_all_players_interval_ms)
+        }
+    };
+    elapsed
+        .min(Duration::from_millis(config.max_tick_dt_ms))
+        .as_secs_f32()
+}
+
+/// Collects the inputs received since the last tick, keyed by player_id.
+fn take_player_inputs(ctx: &ReducerContext) -> HashMap<u32, Input> {
+    let mut inputs = HashMap::new();
+    for mut input in ctx.db.player_input().iter().filter(|i| i.pending) {
+        inputs.insert(
+            input.player_id,
+            Input {
+                direction: input.direction.into(),
+                speed: input.speed,
+            },
+        );
+        input.pending = false;
+        ctx.db.player_input().identity().update(input);
+    }
+    inputs
+}
+
+/// Loads every entity of the arena into a simulation `World`.
+fn load_world(ctx: &ReducerContext, arena: &Arena) -> World {
+    let mut world = World::new(arena.world_size as f32);
+    world.player_teams = ctx
         .db
-        .circle()
-        .iter()
-        .map(|c| (c.entity_id, c.direction * c.speed))
+        .player()
+        .arena_id()
+        .filter(arena.arena_id)
+        .map(|p| (p.player_id, p.team_id))
         .collect();
+    for entity in ctx.db.entity().arena_id().filter(arena.arena_id) {
+        let kind = if let Some(circle) = ctx.db.circle().entity_id().find(entity.entity_id) {
+            EntityKind::Circle {
+                player_id: circle.player_id,
+                direction: circle.direction.into(),
+                speed: circle.speed,
+                split_age_sec: ctx
+                    .timestamp
+                    .duration_since(circle.last_split_time)
+                    .unwrap_or(Duration::ZERO)
+                    .as_secs_f32(),
+            }
+        } else if let Some(ejected) = ctx.db.ejected_mass().entity_id().find(entity.entity_id) {
+            EntityKind::EjectedMass {
+                velocity: ejected.velocity.into(),
+            }
+        } else if ctx.db.virus().entity_id().find(entity.entity_id).is_some() {
+            EntityKind::Virus
+        } else {
+            EntityKind::Food
+        };
+        world.insert(agario_sim::Entity {
+            entity_id: entity.entity_id,
+            position: entity.position.into(),
+            mass: entity.mass,
+            kind,
+        });
+    }
+    world
+}
 
-    // Split circle movement
-    for player in ctx.db.player().iter() {
-        let circles: Vec<Circle> = ctx
-            .db
-            .circle()
-            .player_id()
-            .filter(&player.player_id)
-            .collect();
-        let mut player_entities: Vec<Entity> = circles
-            .iter()
-            .map(|c| ctx.db.entity().entity_id().find(&c.entity_id).unwrap())
-            .collect();
-        if player_entities.len() <= 1 {
+fn move_arena_players(
+    ctx: &ReducerContext,
+    config: &Config,
+    sim_config: &SimConfig,
+    dt: f32,
+    arena: &Arena,
+    inputs: &HashMap<u32, Input>,
+) {
+    let mut world = load_world(ctx, arena);
+    let before = world.entities.clone();
+    let contacts = world.step(sim_config, dt, inputs);
+
+    // Only write back the rows the step moved or steered, eaten ones are handled below
+    for (entity_id, entity) in world.entities.iter() {
+        let old = &before[entity_id];
+        if entity.position != old.position {
+            // Also moves the entity to its new chunk when it crossed a cell boundary
+            ctx.db.entity().entity_id().update(Entity {
+                entity_id: *entity_id,
+                ..Entity::new(config, arena.arena_id, entity.position.into(), entity.mass)
+            });
+        }
+        // split_age_sec advances every step but isn't stored, so it doesn't count as a change
+        match (entity.kind, old.kind) {
+            (
+                EntityKind::Circle {
+                    direction, speed, ..
+                },
+                EntityKind::Circle {
+                    direction: old_direction,
+                    speed: old_speed,
+                    ..
+                },
+            ) => {
+                if direction == old_direction && speed == old_speed {
+                    continue;
+                }
+                let Some(mut circle) = ctx.db.circle().entity_id().find(entity_id) else {
+                    continue;
+                };
+                circle.direction = direction.into();
+                circle.speed = speed;
+                ctx.db.circle().entity_id().update(circle);
+            }
+            (EntityKind::EjectedMass { velocity }, old_kind) => {
+                if old_kind == entity.kind {
+                    continue;
+                }
+                ctx.db.ejected_mass().entity_id().update(EjectedMass {
+                    entity_id: *entity_id,
+                    arena_id: arena.arena_id,
+                    velocity: velocity.into(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // Decide who eats what on the world first so the order doesn't depend on the tables
+    let eaten = world.resolve_contacts(contacts);
+    for contact in eaten.iter() {
+        if let Err(err) =
+            consume_entity(ctx, contact.consumer_entity_id, contact.consumed_entity_id)
+        {
+            log::warn!("Failed to resolve {:?}: {}", contact, err);
+        }
+    }
+    // Circles that swallowed a virus burst once everything else is eaten
+    for contact in eaten {
+        if before[&contact.consumed_entity_id].kind != EntityKind::Virus {
+            continue;
+        }
+        let splits = world.pop(sim_config, contact.consumer_entity_id);
+        if let Err(err) = insert_splits(ctx, config, &world, arena.arena_id, &splits) {
+            log::warn!(
+                "Failed to pop circle {}: {}",
+                contact.consumer_entity_id,
+                err
+            );
+        }
+    }
+
+    // Record new mass peaks
+    let mut player_masses: HashMap<u32, u32> = HashMap::new();
+    for entity in world.entities.values() {
+        if let Some(player_id) = entity.player_id() {
+            *player_masses.entry(player_id).or_default() += entity.mass;
+        }
+    }
+    for (player_id, mass) in player_masses {
+        let Some(mut stats) = ctx.db.player_stats().player_id().find(player_id) else {
             continue;
+        };
+        if mass > stats.current_life_peak_mass {
+            stats.current_life_peak_mass = mass;
+            stats.all_time_high_mass = stats.all_time_high_mass.max(mass);
+            ctx.db.player_stats().player_id().update(stats);
         }
-        let count = player_entities.len();
+    }
 
-        // Gravitate circles towards other circles before they recombine
-        for i in 0..player_entities.len() {
-            let circle_i = &circles[i];
-            let time_since_split = ctx
-                .timestamp
-                .duration_since(circle_i.last_split_time)
-                .unwrap()
-                .as_secs_f32();
-            let time_before_recombining = (SPLIT_RECOMBINE_DELAY_SEC - time_since_split).max(0.0);
-            if time_before_recombining > SPLIT_GRAV_PULL_BEFORE_RECOMBINE_SEC {
-                continue;
-            }
+    update_arena_player_views(ctx, config, arena);
+}
 
-            let (slice1, slice_i) = player_entities.split_at_mut(i);
-            let (slice_i, slice2) = slice_i.split_at_mut(1);
-            let entity_i = &mut slice_i[0];
-            for entity_j in slice1.iter().chain(slice2.iter()) {
-                let mut diff = entity_i.position - entity_j.position;
-                let mut distance_sqr = diff.sqr_magnitude();
-                if distance_sqr <= 0.0001 {
-                    diff = DbVector2::new(1.0, 0.0);
-                    distance_sqr = 1.0;
-                }
-                let radius_sum = mass_to_radius(entity_i.mass) + mass_to_radius(entity_j.mass);
-                if distance_sqr > radius_sum * radius_sum {
-                    let gravity_multiplier =
-                        1.0 - time_before_recombining / SPLIT_GRAV_PULL_BEFORE_RECOMBINE_SEC;
-                    let vec = diff.normalized()
-                        * (radius_sum - distance_sqr.sqrt())
-                        * gravity_multiplier
-                        * 0.05
-                        / count as f32;
-                    *circle_directions.get_mut(&entity_i.entity_id).unwrap() += vec / 2.0;
-                    *circle_directions.get_mut(&entity_j.entity_id).unwrap() -= vec / 2.0;
-                }
-            }
+fn update_arena_player_views(ctx: &ReducerContext, config: &Config, arena: &Arena) {
+    // Read the tables rather than the world so circles from virus pops are included
+    let mut player_entities: HashMap<u32, Vec<Entity>> = HashMap::new();
+    for circle in ctx.db.circle().arena_id().filter(arena.arena_id) {
+        if let Some(entity) = ctx.db.entity().entity_id().find(circle.entity_id) {
+            player_entities
+                .entry(circle.player_id)
+                .or_default()
+                .push(entity);
         }
+    }
 
-        // Force circles apart
-        for i in 0..player_entities.len() {
-            let (slice1, slice2) = player_entities.split_at_mut(i + 1);
-            let entity_i = &mut slice1[i];
-            for j in 0..slice2.len() {
-                let entity_j = &mut slice2[j];
-                let mut diff = entity_i.position - entity_j.position;
-                let mut distance_sqr = diff.sqr_magnitude();
-                if distance_sqr <= 0.0001 {
-                    diff = DbVector2::new(1.0, 0.0);
-                    distance_sqr = 1.0;
-                }
-                let radius_sum = mass_to_radius(entity_i.mass) + mass_to_radius(entity_j.mass);
-                let radius_sum_multiplied = radius_sum * ALLOWED_SPLIT_CIRCLE_OVERLAP_PCT;
-                if distance_sqr < radius_sum_multiplied * radius_sum_multiplied {
-                    let vec = diff.normalized()
-                        * (radius_sum - distance_sqr.sqrt())
-                        * SELF_COLLISION_SPEED;
-                    *circle_directions.get_mut(&entity_i.entity_id).unwrap() += vec / 2.0;
-                    *circle_directions.get_mut(&entity_j.entity_id).unwrap() -= vec / 2.0;
-                }
+    for view in ctx.db.player_view().arena_id().filter(arena.arena_id) {
+        if !player_entities.contains_key(&view.player_id) {
+            ctx.db.player_view().player_id().delete(view.player_id);
+        }
+    }
+    for (player_id, entities) in player_entities {
+        let view = player_view(config, arena.arena_id, player_id, &entities);
+        match ctx.db.player_view().player_id().find(player_id) {
+            Some(existing) if existing == view => {}
+            Some(_) => {
+                ctx.db.player_view().player_id().update(view);
+            }
+            None => {
+                ctx.db.player_view().insert(view);
             }
         }
     }
+}
 
-    // Handle player input
-    for circle in ctx.db.circle().iter() {
-        let circle_entity = ctx.db.entity().entity_id().find(&circle.entity_id);
-        if !circle_entity.is_some() {
-            // This can happen if a circle is eaten by another circle
+/// Brings `visible_entity` in line with what every player and spectator in
+/// the arena can currently see.
+fn update_arena_visibility(ctx: &ReducerContext, config: &Config, arena: &Arena) {
+    let views: HashMap<u32, PlayerView> = ctx
+        .db
+        .player_view()
+        .arena_id()
+        .filter(arena.arena_id)
+        .map(|view| (view.player_id, view))
+        .collect();
+
+    // Where each camera in the arena is and how far it sees
+    let mut cameras: HashMap<Identity, (DbVector2, f32)> = HashMap::new();
+    for player in ctx.db.player().arena_id().filter(arena.arena_id) {
+        if player.is_bot {
             continue;
         }
-        let mut circle_entity = circle_entity.unwrap();
-        let circle_radius = mass_to_radius(circle_entity.mass);
-        let direction = *circle_directions.get(&circle.entity_id).unwrap();
-        let new_pos =
-            circle_entity.position + direction * mass_to_max_move_speed(circle_entity.mass);
-        let min = circle_radius;
-        let max = world_size as f32 - circle_radius;
-        circle_entity.position.x = new_pos.x.clamp(min, max);
-        circle_entity.position.y = new_pos.y.clamp(min, max);
-        ctx.db.entity().entity_id().update(circle_entity);
+        if let Some(view) = views.get(&player.player_id) {
+            cameras.insert(player.identity, (view.center_of_mass, view.view_radius));
+        }
+    }
+    for spectator in ctx.db.spectator().arena_id().filter(arena.arena_id) {
+        let view_radius = views
+            .get(&spectator.followed_player_id)
+            .map_or(config.view_radius_base, |view| view.view_radius);
+        cameras.insert(spectator.identity, (spectator.camera_center, view_radius));
     }
 
-    // Check collisions
-    let entities: HashMap<u32, Entity> = ctx.db.entity().iter().map(|e| (e.entity_id, e)).collect();
-    for circle in ctx.db.circle().iter() {
-        // let span = spacetimedb::time_span::Span::start("collisions");
-        let circle_entity = entities.get(&circle.entity_id).unwrap();
-        for (_, other_entity) in entities.iter() {
-            if other_entity.entity_id == circle_entity.entity_id {
-                continue;
-            }
+    let mut visible: HashMap<Identity, HashSet<u32>> = HashMap::new();
+    for (&identity, &(center, view_radius)) in cameras.iter() {
+        visible.insert(
+            identity,
+            entities_in_range(
+                ctx,
+                config,
+                arena,
+                center,
+                view_radius + config.visibility_margin,
+            ),
+        );
+    }
 
-            if is_overlapping(&circle_entity, other_entity) {
-                let other_circle = ctx.db.circle().entity_id().find(&other_entity.entity_id);
-                if let Some(other_circle) = other_circle {
-                    if other_circle.player_id != circle.player_id {
-                        let mass_ratio = other_entity.mass as f32 / circle_entity.mass as f32;
-                        if mass_ratio < MINIMUM_SAFE_MASS_RATIO {
-                            schedule_consume_entity(
-                                ctx,
-                                circle_entity.entity_id,
-                                other_entity.entity_id,
-                            );
-                        }
-                    }
-                } else {
-                    schedule_consume_entity(ctx, circle_entity.entity_id, other_entity.entity_id);
+    // Drop rows that went out of view, and those of anyone no longer watching
+    for row in ctx.db.visible_entity().arena_id().filter(arena.arena_id) {
+        let still_visible = visible
+            .get_mut(&row.identity)
+            .is_some_and(|entity_ids| entity_ids.remove(&row.entity_id));
+        if !still_visible {
+            ctx.db.visible_entity().row_id().delete(row.row_id);
+        }
+    }
+    // Whatever is left came into view this tick
+    for (identity, entity_ids) in visible {
+        for entity_id in entity_ids {
+            ctx.db.visible_entity().insert(VisibleEntity {
+                row_id: 0,
+                identity,
+                entity_id,
+                arena_id: arena.arena_id,
+            });
+        }
+    }
+}
+
+/// Entities in the arena with any part within `range` of `center`, looked up
+/// through the chunks the range covers.
+fn entities_in_range(
+    ctx: &ReducerContext,
+    config: &Config,
+    arena: &Arena,
+    center: DbVector2,
+    range: f32,
+) -> HashSet<u32> {
+    let min = chunk_id(config.chunk_size, center - DbVector2::new(range, range));
+    let max = chunk_id(config.chunk_size, center + DbVector2::new(range, range));
+    let mut entity_ids = HashSet::new();
+    for cell_y in (min >> 16)..=(max >> 16) {
+        for cell_x in (min & 0xffff)..=(max & 0xffff) {
+            for entity in ctx.db.entity().chunk_id().filter(cell_y << 16 | cell_x) {
+                if entity.arena_id != arena.arena_id {
+                    continue;
+                }
+                let reach = range + mass_to_radius(entity.mass);
+                if (entity.position - center).sqr_magnitude() <= reach * reach {
+                    entity_ids.insert(entity.entity_id);
                 }
             }
         }
-        // span.end();
     }
-
-    // span.end();
-    Ok(())
+    entity_ids
 }
 
-fn schedule_consume_entity(ctx: &ReducerContext, consumer_id: u32, consumed_id: u32) {
-    ctx.db.consume_entity_timer().insert(ConsumeEntityTimer {
-        scheduled_id: 0,
-        scheduled_at: ScheduleAt::Time(ctx.timestamp.clone()),
-        consumer_entity_id: consumer_id,
-        consumed_entity_id: consumed_id,
-    });
+fn player_view(config: &Config, arena_id: u32, player_id: u32, entities: &[Entity]) -> PlayerView {
+    let center_of_mass = calculate_center_of_mass(entities);
+    let mut extent_min = DbVector2::new(f32::MAX, f32::MAX);
+    let mut extent_max = DbVector2::new(f32::MIN, f32::MIN);
+    for entity in entities {
+        let radius = mass_to_radius(entity.mass);
+        extent_min.x = extent_min.x.min(entity.position.x - radius);
+        extent_min.y = extent_min.y.min(entity.position.y - radius);
+        extent_max.x = extent_max.x.max(entity.position.x + radius);
+        extent_max.y = extent_max.y.max(entity.position.y + radius);
+    }
+
+    let total_mass: u32 = entities.iter().map(|e| e.mass).sum();
+    let extra_circles = entities.len().saturating_sub(1) as f32;
+    let view_radius = config.view_radius_base
+        + (total_mass as f32).sqrt() * config.view_radius_per_sqrt_mass
+        + extra_circles * config.view_radius_per_extra_circle;
+    // Never crop the player's own circles out of view
+    let farthest_corner = [
+        extent_min,
+        extent_max,
+        DbVector2::new(extent_min.x, extent_max.y),
+        DbVector2::new(extent_max.x, extent_min.y),
+    ]
+    .into_iter()
+    .map(|corner| (corner - center_of_mass).magnitude())
+    .fold(0.0, f32::max);
+
+    PlayerView {
+        player_id,
+        arena_id,
+        center_of_mass,
+        extent_min,
+        extent_max,
+        view_radius: view_radius.max(farthest_corner),
+    }
 }
 
-#[spacetimedb::reducer]
-pub fn consume_entity(ctx: &ReducerContext, request: ConsumeEntityTimer) -> Result<(), String> {
-    let consumed_entity = ctx
-        .db
-        .entity()
-        .entity_id()
-        .find(&request.consumed_entity_id);
-    let consumer_entity = ctx
-        .db
-        .entity()
-        .entity_id()
-        .find(&request.consumer_entity_id);
+fn consume_entity(
+    ctx: &ReducerContext,
+    consumer_entity_id: u32,
+    consumed_entity_id: u32,
+) -> Result<(), String> {
+    let consumed_entity = ctx.db.entity().entity_id().find(consumed_entity_id);
+    let consumer_entity = ctx.db.entity().entity_id().find(consumer_entity_id);
     if consumed_entity.is_none() {
         return Err("Consumed entity doesn't exist".into());
     }
@@ -490,112 +2009,266 @@ pub fn consume_entity(ctx: &ReducerContext, request: ConsumeEntityTimer) -> Resu
     }
     let consumed_entity = consumed_entity.unwrap();
     let mut consumer_entity = consumer_entity.unwrap();
+    let consumed_food = ctx
+        .db
+        .food()
+        .entity_id()
+        .find(consumed_entity.entity_id)
+        .is_some();
+    let consumed_circle = ctx.db.circle().entity_id().find(consumed_entity.entity_id);
+    let consumer_player_id = ctx
+        .db
+        .circle()
+        .entity_id()
+        .find(consumer_entity.entity_id)
+        .map(|c| c.player_id);
 
     consumer_entity.mass += consumed_entity.mass;
     destroy_entity(ctx, consumed_entity.entity_id)?;
     ctx.db.entity().entity_id().update(consumer_entity);
 
+    if let Some(consumer_player_id) = consumer_player_id {
+        if consumed_food {
+            update_player_stats(ctx, consumer_player_id, |stats| stats.food_eaten += 1);
+        }
+        if let Some(victim) = consumed_circle.filter(|c| c.player_id != consumer_player_id) {
+            let last_circle = ctx
+                .db
+                .circle()
+                .player_id()
+                .filter(victim.player_id)
+                .next()
+                .is_none();
+            ctx.db.kill_event().insert(KillEvent {
+                event_id: 0,
+                timestamp: ctx.timestamp,
+                killer_player_id: consumer_player_id,
+                victim_player_id: victim.player_id,
+                mass: consumed_entity.mass,
+                last_circle,
+            });
+            if last_circle {
+                update_player_stats(ctx, consumer_player_id, |stats| stats.kills += 1);
+            }
+        }
+    }
+
     Ok(())
 }
 
 pub fn destroy_entity(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
-    ctx.db.food().entity_id().delete(&entity_id);
-    ctx.db.circle().entity_id().delete(&entity_id);
-    ctx.db.entity().entity_id().delete(&entity_id);
+    ctx.db.food().entity_id().delete(entity_id);
+    ctx.db.virus().entity_id().delete(entity_id);
+    ctx.db.ejected_mass().entity_id().delete(entity_id);
+    let circle = ctx.db.circle().entity_id().find(entity_id);
+    ctx.db.circle().entity_id().delete(entity_id);
+    ctx.db.entity().entity_id().delete(entity_id);
+
+    if let Some(circle) = circle {
+        if ctx
+            .db
+            .circle()
+            .player_id()
+            .filter(circle.player_id)
+            .next()
+            .is_none()
+        {
+            on_player_died(ctx, circle.player_id);
+        }
+    }
 
     Ok(())
 }
 
+fn on_player_died(ctx: &ReducerContext, player_id: u32) {
+    let Some(mut player) = ctx.db.player().player_id().filter(player_id).next() else {
+        return;
+    };
+    if player.state == PlayerState::Alive {
+        player.state = PlayerState::Dead;
+        ctx.db.player().identity().update(player);
+        end_player_life(ctx, player_id, true);
+    }
+}
+
 #[spacetimedb::reducer]
 pub fn player_split(ctx: &ReducerContext) -> Result<(), String> {
     let player = ctx
         .db
         .player()
         .identity()
-        .find(&ctx.sender)
+        .find(ctx.sender)
         .ok_or("Sender has no player")?;
-    let circles: Vec<Circle> = ctx
+    let config = get_config(ctx)?;
+    let arena = get_arena(ctx, player.arena_id)?;
+    split_player_circles(ctx, &config, &arena, player.player_id)?;
+
+    log::warn!("Player split!");
+
+    Ok(())
+}
+
+fn split_player_circles(
+    ctx: &ReducerContext,
+    config: &Config,
+    arena: &Arena,
+    player_id: u32,
+) -> Result<(), String> {
+    let mut world = load_world(ctx, arena);
+    let splits = world.split(&config.sim_config(), player_id);
+    insert_splits(ctx, config, &world, arena.arena_id, &splits)
+}
+
+/// Writes the outcome of `World::split` or `World::pop` back to the tables:
+/// inserts the new circles, shrinks their parents and restarts the parents'
+/// recombine delay.
+fn insert_splits(
+    ctx: &ReducerContext,
+    config: &Config,
+    world: &World,
+    arena_id: u32,
+    splits: &[Split],
+) -> Result<(), String> {
+    let Some(player_id) = splits.first().and_then(|split| split.piece.player_id()) else {
+        return Ok(());
+    };
+    for split in splits {
+        spawn_circle_at(
+            ctx,
+            player_id,
+            arena_id,
+            split.piece.mass,
+            split.piece.position.into(),
+            ctx.timestamp,
+        )?;
+    }
+    let parent_ids: HashSet<u32> = splits.iter().map(|s| s.parent_entity_id).collect();
+    for parent_id in parent_ids {
+        let mut entity = ctx
+            .db
+            .entity()
+            .entity_id()
+            .find(parent_id)
+            .ok_or("Circle has no entity")?;
+        entity.mass = world.entities[&parent_id].mass;
+        ctx.db.entity().entity_id().update(entity);
+        let mut circle = ctx
+            .db
+            .circle()
+            .entity_id()
+            .find(parent_id)
+            .ok_or("Circle not found")?;
+        circle.last_split_time = ctx.timestamp;
+        ctx.db.circle().entity_id().update(circle);
+    }
+
+    schedule_circle_recombine(ctx, config, player_id);
+
+    Ok(())
+}
+
+#[spacetimedb::reducer]
+pub fn eject_mass(ctx: &ReducerContext) -> Result<(), String> {
+    let mut player = ctx
         .db
-        .circle()
-        .player_id()
-        .filter(&player.player_id)
-        .collect();
-    let mut circle_count = circles.len() as u32;
-    if circle_count >= MAX_CIRCLES_PER_PLAYER {
+        .player()
+        .identity()
+        .find(ctx.sender)
+        .ok_or("Sender has no player")?;
+    let config = get_config(ctx)?;
+    let since_last_eject = ctx
+        .timestamp
+        .duration_since(player.last_eject_time)
+        .unwrap_or(Duration::ZERO);
+    if since_last_eject < Duration::from_millis(config.eject_cooldown_ms) {
         return Ok(());
     }
 
-    for mut circle in circles {
+    let ejected_radius = mass_to_radius(config.ejected_mass);
+    for circle in ctx.db.circle().player_id().filter(player.player_id) {
+        if circle.direction.sqr_magnitude() == 0.0 {
+            continue;
+        }
         let mut circle_entity = ctx
             .db
             .entity()
             .entity_id()
-            .find(&circle.entity_id)
+            .find(circle.entity_id)
             .ok_or("Circle has no entity")?;
-        if circle_entity.mass >= MIN_MASS_TO_SPLIT * 2 {
-            let half_mass = circle_entity.mass / 2;
-            spawn_circle_at(
-                ctx,
-                circle.player_id,
-                half_mass,
-                circle_entity.position + circle.direction,
-                ctx.timestamp,
-            )?;
-            circle_entity.mass -= half_mass;
-            circle.last_split_time = ctx.timestamp;
-            ctx.db.circle().entity_id().update(circle);
-            ctx.db.entity().entity_id().update(circle_entity);
-            circle_count += 1;
-            if circle_count >= MAX_CIRCLES_PER_PLAYER {
-                break;
-            }
+        if circle_entity.mass < config.min_mass_to_eject {
+            continue;
         }
+
+        // Spawn the pellet just outside the circle so it isn't eaten straight back
+        let offset = mass_to_radius(circle_entity.mass) + ejected_radius;
+        let entity = ctx.db.entity().try_insert(Entity::new(
+            &config,
+            circle.arena_id,
+            circle_entity.position + circle.direction * offset,
+            config.ejected_mass,
+        ))?;
+        ctx.db.ejected_mass().try_insert(EjectedMass {
+            entity_id: entity.entity_id,
+            arena_id: circle.arena_id,
+            velocity: circle.direction * config.eject_speed,
+        })?;
+        circle_entity.mass -= config.ejected_mass;
+        ctx.db.entity().entity_id().update(circle_entity);
     }
 
+    player.last_eject_time = ctx.timestamp;
+    ctx.db.player().identity().update(player);
+
+    Ok(())
+}
+
+fn schedule_circle_recombine(ctx: &ReducerContext, config: &Config, player_id: u32) {
     ctx.db
         .circle_recombine_timer()
         .insert(CircleRecombineTimer {
             scheduled_id: 0,
             scheduled_at: ScheduleAt::Time(
-                ctx.timestamp + TimeDuration::from(Duration::from_secs_f32(SPLIT_RECOMBINE_DELAY_SEC))
+                ctx.timestamp
+                    + TimeDuration::from(Duration::from_secs_f32(config.split_recombine_delay_sec)),
             ),
-            player_id: player.player_id,
+            player_id,
         });
-
-    log::warn!("Player split!");
-
-    Ok(())
 }
 
 #[spacetimedb::reducer]
 pub fn spawn_food(ctx: &ReducerContext, _timer: SpawnFoodTimer) -> Result<(), String> {
-    if ctx.db.player().count() == 0 {
-        //Are there no players yet?
-        return Ok(());
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    for arena in ctx.db.arena().iter() {
+        if !arena_has_active_players(ctx, arena.arena_id) {
+            //Are there no players yet?
+            continue;
+        }
+        spawn_arena_food(ctx, &config, &arena)?;
     }
 
-    let world_size = ctx
-        .db
-        .config()
-        .id()
-        .find(0)
-        .ok_or("Config not found")?
-        .world_size;
+    Ok(())
+}
+
+fn spawn_arena_food(ctx: &ReducerContext, config: &Config, arena: &Arena) -> Result<(), String> {
+    let world_size = arena.world_size;
 
     let mut rng = ctx.rng();
-    let mut food_count = ctx.db.food().count();
-    while food_count < TARGET_FOOD_COUNT as u64 {
-        let food_mass = rng.gen_range(FOOD_MASS_MIN..FOOD_MASS_MAX);
+    let mut food_count = ctx.db.food().arena_id().filter(arena.arena_id).count();
+    while food_count < arena.target_food_count as usize {
+        let food_mass = rng.gen_range(config.food_mass_min..config.food_mass_max);
         let food_radius = mass_to_radius(food_mass);
         let x = rng.gen_range(food_radius..world_size as f32 - food_radius);
         let y = rng.gen_range(food_radius..world_size as f32 - food_radius);
-        let entity = ctx.db.entity().try_insert(Entity {
-            entity_id: 0,
-            position: DbVector2 { x, y },
-            mass: food_mass,
-        })?;
+        let entity = ctx.db.entity().try_insert(Entity::new(
+            config,
+            arena.arena_id,
+            DbVector2 { x, y },
+            food_mass,
+        ))?;
         ctx.db.food().try_insert(Food {
             entity_id: entity.entity_id,
+            arena_id: arena.arena_id,
         })?;
         food_count += 1;
         log::info!("Spawned food! {}", entity.entity_id);
@@ -604,25 +2277,207 @@ pub fn spawn_food(ctx: &ReducerContext, _timer: SpawnFoodTimer) -> Result<(), St
     Ok(())
 }
 
+#[spacetimedb::reducer]
+pub fn spawn_virus(ctx: &ReducerContext, _timer: SpawnVirusTimer) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    for arena in ctx.db.arena().iter() {
+        if !arena_has_active_players(ctx, arena.arena_id) {
+            //Are there no players yet?
+            continue;
+        }
+        spawn_arena_viruses(ctx, &config, &arena)?;
+    }
+
+    Ok(())
+}
+
+fn spawn_arena_viruses(ctx: &ReducerContext, config: &Config, arena: &Arena) -> Result<(), String> {
+    let world_size = arena.world_size;
+
+    let mut rng = ctx.rng();
+    let mut virus_count = ctx.db.virus().arena_id().filter(arena.arena_id).count();
+    let virus_radius = mass_to_radius(config.virus_mass);
+    while virus_count < arena.target_virus_count as usize {
+        let x = rng.gen_range(virus_radius..world_size as f32 - virus_radius);
+        let y = rng.gen_range(virus_radius..world_size as f32 - virus_radius);
+        let entity = ctx.db.entity().try_insert(Entity::new(
+            config,
+            arena.arena_id,
+            DbVector2 { x, y },
+            config.virus_mass,
+        ))?;
+        ctx.db.virus().try_insert(Virus {
+            entity_id: entity.entity_id,
+            arena_id: arena.arena_id,
+        })?;
+        virus_count += 1;
+        log::info!("Spawned virus! {}", entity.entity_id);
+    }
+
+    Ok(())
+}
+
 #[spacetimedb::reducer]
 pub fn circle_decay(ctx: &ReducerContext, _timer: CircleDecayTimer) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?.sim_config();
     for circle in ctx.db.circle().iter() {
         let mut circle_entity = ctx
             .db
             .entity()
             .entity_id()
-            .find(&circle.entity_id)
+            .find(circle.entity_id)
             .ok_or("Entity not found")?;
-        if circle_entity.mass <= START_PLAYER_MASS {
+        let mass = decayed_mass(&config, circle_entity.mass);
+        if mass == circle_entity.mass {
             continue;
         }
-        circle_entity.mass = (circle_entity.mass as f32 * 0.99) as u32;
+        circle_entity.mass = mass;
         ctx.db.entity().entity_id().update(circle_entity);
     }
 
     Ok(())
 }
 
+#[spacetimedb::reducer]
+pub fn update_leaderboard(
+    ctx: &ReducerContext,
+    _timer: UpdateLeaderboardTimer,
+) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let leaderboard_size = get_config(ctx)?.leaderboard_size as usize;
+    let player_masses = total_mass_by_player(ctx, ctx.db.circle().iter());
+
+    // arena_id -> the arena's players with circles
+    let mut standings: HashMap<u32, Vec<LeaderboardEntry>> = HashMap::new();
+    for player in ctx.db.player().iter() {
+        if player.state == PlayerState::Spectating {
+            continue;
+        }
+        let Some(&mass) = player_masses.get(&player.player_id) else {
+            continue;
+        };
+        standings
+            .entry(player.arena_id)
+            .or_default()
+            .push(LeaderboardEntry {
+                entry_id: 0,
+                arena_id: player.arena_id,
+                rank: 0,
+                player_id: player.player_id,
+                name: player.name,
+                mass,
+            });
+    }
+
+    // Only touch the rows that changed so subscribers don't get a full refresh every second
+    let mut existing: HashMap<(u32, u32), LeaderboardEntry> = ctx
+        .db
+        .leaderboard()
+        .iter()
+        .map(|entry| ((entry.arena_id, entry.rank), entry))
+        .collect();
+    for mut arena_standings in standings.into_values() {
+        arena_standings.sort_by(|a, b| b.mass.cmp(&a.mass).then(a.player_id.cmp(&b.player_id)));
+        arena_standings.truncate(leaderboard_size);
+        for (i, mut entry) in arena_standings.into_iter().enumerate() {
+            entry.rank = i as u32 + 1;
+            match existing.remove(&(entry.arena_id, entry.rank)) {
+                Some(old) => {
+                    entry.entry_id = old.entry_id;
+                    if old != entry {
+                        ctx.db.leaderboard().entry_id().update(entry);
+                    }
+                }
+                None => {
+                    ctx.db.leaderboard().insert(entry);
+                }
+            }
+        }
+    }
+    // Whatever is left fell off its arena's board, or its arena is empty or gone
+    for entry in existing.into_values() {
+        ctx.db.leaderboard().entry_id().delete(entry.entry_id);
+    }
+
+    // team_id -> (member_count, total_mass)
+    let mut team_totals: HashMap<u32, (u32, u32)> = HashMap::new();
+    for player in ctx.db.player().iter() {
+        if player.team_id == NO_TEAM {
+            continue;
+        }
+        let totals = team_totals.entry(player.team_id).or_default();
+        totals.0 += 1;
+        totals.1 += player_masses.get(&player.player_id).copied().unwrap_or(0);
+    }
+    for mut team in ctx.db.team().iter() {
+        let (member_count, total_mass) =
+            team_totals.get(&team.team_id).copied().unwrap_or_default();
+        if team.member_count != member_count || team.total_mass != total_mass {
+            team.member_count = member_count;
+            team.total_mass = total_mass;
+            ctx.db.team().team_id().update(team);
+        }
+    }
+
+    Ok(())
+}
+
+#[spacetimedb::reducer]
+pub fn trim_kill_events(ctx: &ReducerContext, _timer: TrimKillEventsTimer) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let retention = Duration::from_secs(get_config(ctx)?.kill_event_retention_sec);
+    for event in ctx.db.kill_event().iter() {
+        let age = ctx
+            .timestamp
+            .duration_since(event.timestamp)
+            .unwrap_or(Duration::ZERO);
+        if age > retention {
+            ctx.db.kill_event().event_id().delete(event.event_id);
+        }
+    }
+
+    Ok(())
+}
+
+#[spacetimedb::reducer]
+pub fn trim_chat_messages(
+    ctx: &ReducerContext,
+    _timer: TrimChatMessagesTimer,
+) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let retention = Duration::from_secs(get_config(ctx)?.chat_message_retention_sec);
+    for message in ctx.db.chat_message().iter() {
+        let age = ctx
+            .timestamp
+            .duration_since(message.timestamp)
+            .unwrap_or(Duration::ZERO);
+        if age > retention {
+            ctx.db
+                .chat_message()
+                .message_id()
+                .delete(message.message_id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sums the live mass of the given circles per player_id.
+fn total_mass_by_player(
+    ctx: &ReducerContext,
+    circles: impl Iterator<Item = Circle>,
+) -> HashMap<u32, u32> {
+    let mut player_masses: HashMap<u32, u32> = HashMap::new();
+    for circle in circles {
+        if let Some(entity) = ctx.db.entity().entity_id().find(circle.entity_id) {
+            *player_masses.entry(circle.player_id).or_default() += entity.mass;
+        }
+    }
+    player_masses
+}
+
 pub fn calculate_center_of_mass(entities: &[Entity]) -> DbVector2 {
     let total_mass: u32 = entities.iter().map(|e| e.mass).sum();
     let center_of_mass: DbVector2 = entities.iter().map(|e| e.position * e.mass as f32).sum();
@@ -631,31 +2486,203 @@ pub fn calculate_center_of_mass(entities: &[Entity]) -> DbVector2 {
 
 #[spacetimedb::reducer]
 pub fn circle_recombine(ctx: &ReducerContext, timer: CircleRecombineTimer) -> Result<(), String> {
-    let circles: Vec<Circle> = ctx
-        .db
-        .circle()
-        .player_id()
-        .filter(&timer.player_id)
-        .collect();
-    let recombining_entities: Vec<Entity> = circles
-        .iter()
-        .filter(|c| {
-            ctx.timestamp
-                .duration_since(c.last_split_time)
-                .unwrap()
-                .as_secs_f32()
-                >= SPLIT_RECOMBINE_DELAY_SEC
-        })
-        .map(|c| ctx.db.entity().entity_id().find(&c.entity_id).unwrap())
-        .collect();
-    if recombining_entities.len() <= 1 {
-        return Ok(()); //No circles to recombine
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    let Some(player) = ctx.db.player().player_id().filter(timer.player_id).next() else {
+        return Ok(()); //Player left in the meantime
+    };
+    let Ok(arena) = get_arena(ctx, player.arena_id) else {
+        return Ok(());
+    };
+    let mut world = load_world(ctx, &arena);
+    for merge in world.recombine(&config.sim_config(), player.player_id) {
+        consume_entity(ctx, merge.consumer_entity_id, merge.consumed_entity_id)?;
     }
 
-    let base_entity_id = recombining_entities[0].entity_id;
-    for i in 1..recombining_entities.len() {
-        schedule_consume_entity(ctx, base_entity_id, recombining_entities[i].entity_id);
+    Ok(())
+}
+
+/// Keeps every arena topped up to `bots_per_arena` minus its human players
+/// and picks a new input for each living bot.
+#[spacetimedb::reducer]
+pub fn update_bots(ctx: &ReducerContext, _timer: UpdateBotsTimer) -> Result<(), String> {
+    ensure_internal_or_admin(ctx)?;
+    let config = get_config(ctx)?;
+    let sim_config = config.sim_config();
+    for arena in ctx.db.arena().iter() {
+        let humans = arena_player_count(ctx, arena.arena_id);
+        let target = config
+            .bots_per_arena
+            .saturating_sub(humans)
+            .min(arena.max_players.saturating_sub(humans)) as usize;
+        let mut bots: Vec<Player> = ctx
+            .db
+            .player()
+            .arena_id()
+            .filter(arena.arena_id)
+            .filter(|p| p.is_bot)
+            .collect();
+        // Remove dead bots first so living ones keep their progress
+        bots.sort_by_key(|bot| (bot.state == PlayerState::Alive, bot.player_id));
+        while bots.len() > target {
+            remove_bot(ctx, bots.remove(0));
+        }
+        for _ in bots.len()..target {
+            spawn_bot(ctx, &arena)?;
+        }
+
+        let world = load_world(ctx, &arena);
+        for mut bot in ctx.db.player().arena_id().filter(arena.arena_id) {
+            if !bot.is_bot {
+                continue;
+            }
+            if bot.state == PlayerState::Dead {
+                spawn_player_initial_circle(ctx, bot.player_id, &arena)?;
+                bot.state = PlayerState::Alive;
+                ctx.db.player().identity().update(bot);
+                continue;
+            }
+            let Some((direction, split)) =
+                choose_bot_move(&sim_config, config.bot_view_distance, &world, bot.player_id)
+            else {
+                continue;
+            };
+            let mut input = ctx
+                .db
+                .player_input()
+                .identity()
+                .find(bot.identity)
+                .unwrap_or(PlayerInput::new(bot.identity, bot.player_id, ctx.timestamp));
+            input.direction = direction.into();
+            input.speed = 1.0;
+            input.pending = true;
+            ctx.db.player_input().identity().delete(bot.identity);
+            ctx.db.player_input().insert(input);
+            if split {
+                split_player_circles(ctx, &config, &arena, bot.player_id)?;
+            }
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn spawn_bot(ctx: &ReducerContext, arena: &Arena) -> Result<(), String> {
+    // Bots never connect, so any identity that can't collide with a real one will do
+    let mut identity = [0u8; 32];
+    ctx.rng().fill(&mut identity[..]);
+    identity[..4].copy_from_slice(b"bot:");
+    let mut bot = ctx.db.player().try_insert(Player {
+        identity: Identity::from_byte_array(identity),
+        player_id: 0,
+        name: String::new(),
+        aptos_address: String::new(),
+        last_eject_time: Timestamp::UNIX_EPOCH,
+        last_chat_time: Timestamp::UNIX_EPOCH,
+        muted_until: None,
+        input_blocked_until: None,
+        state: PlayerState::Alive,
+        arena_id: arena.arena_id,
+        team_id: assign_team(ctx, arena, None)?,
+        is_bot: true,
+    })?;
+    let player_id = bot.player_id;
+    bot.name = format!("Bot {}", player_id);
+    ctx.db.player().identity().update(bot);
+    spawn_player_initial_circle(ctx, player_id, arena)?;
+    Ok(())
+}
+
+fn remove_bot(ctx: &ReducerContext, bot: Player) {
+    if bot.state == PlayerState::Alive {
+        end_player_life(ctx, bot.player_id, false);
+    }
+    for circle in ctx.db.circle().player_id().filter(bot.player_id) {
+        ctx.db.entity().entity_id().delete(circle.entity_id);
+        ctx.db.circle().entity_id().delete(circle.entity_id);
+    }
+    ctx.db.player_input().identity().delete(bot.identity);
+    ctx.db.player_view().player_id().delete(bot.player_id);
+    // Bots come back with a new player_id, so their stats would never be read again
+    ctx.db.player_stats().player_id().delete(bot.player_id);
+    ctx.db.player().identity().delete(bot.identity);
+}
+
+/// Flees anything that could eat the bot's biggest circle, otherwise chases the
+/// closest circle it can eat (splitting onto it when that's enough to eat it)
+/// or heads for the closest food. Returns the direction and whether to split.
+fn choose_bot_move(
+    config: &SimConfig,
+    view_distance: f32,
+    world: &World,
+    player_id: u32,
+) -> Option<(agario_sim::Vec2, bool)> {
+    let circles: Vec<&agario_sim::Entity> = world
+        .entities
+        .values()
+        .filter(|e| e.player_id() == Some(player_id))
+        .collect();
+    let largest = circles.iter().max_by_key(|e| e.mass)?;
+    let total_mass: u32 = circles.iter().map(|e| e.mass).sum();
+    let center: agario_sim::Vec2 = circles
+        .iter()
+        .map(|e| e.position * e.mass as f32)
+        .sum::<agario_sim::Vec2>()
+        / total_mass as f32;
+
+    let mut flee = agario_sim::Vec2::new(0.0, 0.0);
+    let mut prey: Option<(f32, &agario_sim::Entity)> = None;
+    let mut food: Option<(f32, &agario_sim::Entity)> = None;
+    for other in world.entities.values() {
+        let offset = other.position - center;
+        let distance = offset.magnitude();
+        if distance > view_distance {
+            continue;
+        }
+        let closest = |best: Option<(f32, &agario_sim::Entity)>| {
+            best.is_none_or(|(best_distance, _)| distance < best_distance)
+        };
+        match other.kind {
+            EntityKind::Circle {
+                player_id: other_player_id,
+                ..
+            } => {
+                if other_player_id == player_id || world.are_teammates(player_id, other_player_id) {
+                    continue;
+                }
+                if agario_sim::outweighs(config, other.mass, largest.mass) {
+                    // Closer threats matter more
+                    flee -= offset.normalized() / distance.max(1.0);
+                } else if agario_sim::outweighs(config, largest.mass, other.mass) && closest(prey) {
+                    prey = Some((distance, other));
+                }
+            }
+            EntityKind::Food | EntityKind::EjectedMass { .. } => {
+                if closest(food) {
+                    food = Some((distance, other));
+                }
+            }
+            EntityKind::Virus => {}
+        }
+    }
+
+    if flee.sqr_magnitude() > 0.0 {
+        return Some((flee.normalized(), false));
+    }
+    if let Some((distance, target)) = prey {
+        let direction = (target.position - center).normalized();
+        let can_split = circles.len() < config.max_circles_per_player as usize;
+        let split = can_split
+            && split_mass(config, largest.mass).is_some_and(|half_mass| {
+                agario_sim::outweighs(config, half_mass, target.mass)
+                    && distance < mass_to_radius(half_mass) * 4.0
+            });
+        return Some((direction, split));
+    }
+    if let Some((_, target)) = food {
+        return Some(((target.position - center).normalized(), false));
+    }
+    // Nothing in sight, wander towards the middle of the arena
+    let middle = agario_sim::Vec2::new(world.world_size / 2.0, world.world_size / 2.0);
+    Some(((middle - center).normalized(), false))
+}
@@ -1,6 +1,6 @@
 use spacetimedb::SpacetimeType;
 
-#[derive(SpacetimeType, Debug, Clone, Copy)]
+#[derive(SpacetimeType, Debug, Clone, Copy, PartialEq)]
 pub struct DbVector2 {
     pub x: f32,
     pub y: f32,
@@ -116,4 +116,16 @@ impl DbVector2 {
     pub fn normalized(self) -> DbVector2 {
         self / self.magnitude()
     }
-}
\ No newline at end of file
+}
+
+impl From<DbVector2> for agario_sim::Vec2 {
+    fn from(v: DbVector2) -> Self {
+        agario_sim::Vec2::new(v.x, v.y)
+    }
+}
+
+impl From<agario_sim::Vec2> for DbVector2 {
+    fn from(v: agario_sim::Vec2) -> Self {
+        DbVector2::new(v.x, v.y)
+    }
+}